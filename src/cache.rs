@@ -0,0 +1,265 @@
+use crate::Error;
+use md5::{Digest, Md5};
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// An on-disk cache of the responses from the ENA API, so that the same
+/// accessions are not queried again and again across runs. Each response
+/// is kept in a file of its own, named after the accession, the API it came
+/// from and the fields that were requested, and is used until it is older
+/// than the TTL
+#[derive(Debug, Clone)]
+pub struct Cache {
+    /// The directory the responses are kept in
+    pub dir: PathBuf,
+    /// How long a response is used for before ENA is queried again
+    pub ttl: Duration,
+    /// Query ENA even if there is a response in the cache, and replace it
+    pub refresh: bool,
+}
+
+/// The number and size of the responses in the cache
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of responses in the cache
+    pub entries: usize,
+    /// The number of responses that are older than the TTL
+    pub expired: usize,
+    /// The number of temporary files left behind by responses that were
+    /// never finished being written, e.g., because phcue-ck was killed
+    pub temporary: usize,
+    /// The total size of the responses and temporary files, in bytes
+    pub bytes: u64,
+}
+
+impl Cache {
+    /// A cache in the directory, with responses used for as long as the TTL
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self {
+            dir,
+            ttl,
+            refresh: false,
+        }
+    }
+
+    /// The directory the cache is kept in by default, under the user's cache
+    /// directory (e.g., $XDG_CACHE_HOME/phcue-ck on Linux)
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("phcue-ck"))
+    }
+
+    /// The file the response for the accession and fields from the source is
+    /// kept in. The source identifies the API the response came from (its
+    /// URL and the type of result), so that responses from a mirror or for
+    /// another type of result are kept apart. The source and fields are
+    /// hashed, so that any of them have a short file name, and the same
+    /// fields in any order share a file
+    fn path(&self, accession: &str, source: &str, fields: &[String]) -> PathBuf {
+        let mut fields = fields.to_vec();
+        fields.sort();
+        fields.dedup();
+        let key = format!("{}\n{}", source, fields.join(","));
+        let hash = format!("{:x}", Md5::digest(key.as_bytes()));
+        self.dir.join(format!("{}-{}.json", accession, &hash[..16]))
+    }
+
+    /// Whether a file in the cache is older than the TTL
+    fn is_expired(&self, metadata: &std::fs::Metadata) -> bool {
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        age.is_none_or(|age| age > self.ttl)
+    }
+
+    /// The response for the accession and fields from the source, if it is
+    /// in the cache and has not expired
+    pub async fn get(&self, accession: &str, source: &str, fields: &[String]) -> Option<String> {
+        if self.refresh {
+            return None;
+        }
+        let path = self.path(accession, source, fields);
+        let metadata = tokio::fs::metadata(&path).await.ok()?;
+        if self.is_expired(&metadata) {
+            return None;
+        }
+        tokio::fs::read_to_string(&path).await.ok()
+    }
+
+    /// The response for the accession and fields from the source, if it is
+    /// in the cache, however old it is. This is used when ENA cannot be
+    /// queried
+    pub async fn get_stale(
+        &self,
+        accession: &str,
+        source: &str,
+        fields: &[String],
+    ) -> Option<String> {
+        let path = self.path(accession, source, fields);
+        tokio::fs::read_to_string(&path).await.ok()
+    }
+
+    /// Keep the response for the accession and fields from the source. It
+    /// is written to a temporary file first, so that a response that is
+    /// being written is never read by another query
+    pub async fn put(
+        &self,
+        accession: &str,
+        source: &str,
+        fields: &[String],
+        body: &str,
+    ) -> Result<(), Error> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.path(accession, source, fields);
+        let tmp = path.with_extension(format!("{:08x}.tmp", rand::random::<u32>()));
+        tokio::fs::write(&tmp, body).await?;
+        if let Err(e) = tokio::fs::rename(&tmp, &path).await {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// The responses and temporary files in the cache, along with their
+    /// metadata
+    fn entries(&self) -> Result<Vec<(PathBuf, std::fs::Metadata)>, Error> {
+        let dir = match std::fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        for entry in dir {
+            let entry = entry?;
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|ext| ext == "json" || ext == "tmp")
+            {
+                entries.push((path, entry.metadata()?));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Remove all the responses and temporary files from the cache,
+    /// returning how many files there were
+    pub fn clear(&self) -> Result<usize, Error> {
+        let entries = self.entries()?;
+        for (path, _) in &entries {
+            match std::fs::remove_file(path) {
+                // A temporary file may have been renamed into place since
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(entries.len())
+    }
+
+    /// Count the responses in the cache, how many of them have expired, and
+    /// the temporary files left behind
+    pub fn stats(&self) -> Result<CacheStats, Error> {
+        let mut stats = CacheStats::default();
+        for (path, metadata) in self.entries()? {
+            stats.bytes += metadata.len();
+            if path.extension().is_some_and(|ext| ext == "tmp") {
+                stats.temporary += 1;
+                continue;
+            }
+            stats.entries += 1;
+            if self.is_expired(&metadata) {
+                stats.expired += 1;
+            }
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENA: &str = "https://www.ebi.ac.uk/ena/portal/api/filereport?result=read_run";
+
+    fn fields(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_get_put() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().join("cache"), Duration::from_secs(60));
+        let tax_id = fields(&["tax_id", "base_count"]);
+        assert_eq!(cache.get("SRR1234567", ENA, &tax_id).await, None);
+        cache.put("SRR1234567", ENA, &tax_id, "[]").await.unwrap();
+        assert_eq!(
+            cache.get("SRR1234567", ENA, &tax_id).await.as_deref(),
+            Some("[]")
+        );
+        // The same fields in another order share the response
+        let reordered = fields(&["base_count", "tax_id", "tax_id"]);
+        assert_eq!(
+            cache.get("SRR1234567", ENA, &reordered).await.as_deref(),
+            Some("[]")
+        );
+        assert_eq!(cache.get("SRR1234567", ENA, &[]).await, None);
+        assert_eq!(cache.get("SRR7654321", ENA, &tax_id).await, None);
+        // Responses from another API or for another result are kept apart
+        let mirror = "http://127.0.0.1:8080/filereport?result=read_run";
+        assert_eq!(cache.get("SRR1234567", mirror, &tax_id).await, None);
+        let experiments = "https://www.ebi.ac.uk/ena/portal/api/filereport?result=read_experiment";
+        assert_eq!(cache.get("SRR1234567", experiments, &tax_id).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_expired_and_refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = Cache::new(dir.path().to_path_buf(), Duration::from_secs(60));
+        cache.put("SRR1234567", ENA, &[], "[]").await.unwrap();
+        cache.refresh = true;
+        assert_eq!(cache.get("SRR1234567", ENA, &[]).await, None);
+        cache.refresh = false;
+        cache.ttl = Duration::ZERO;
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(cache.get("SRR1234567", ENA, &[]).await, None);
+        assert_eq!(
+            cache.get_stale("SRR1234567", ENA, &[]).await.as_deref(),
+            Some("[]")
+        );
+        assert_eq!(
+            cache.stats().unwrap(),
+            CacheStats {
+                entries: 1,
+                expired: 1,
+                temporary: 0,
+                bytes: 2
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().join("cache"), Duration::from_secs(60));
+        assert_eq!(cache.clear().unwrap(), 0);
+        cache.put("SRR1234567", ENA, &[], "[]").await.unwrap();
+        cache.put("SRR7654321", ENA, &[], "[]").await.unwrap();
+        // A response that was never finished being written
+        let tmp = dir
+            .path()
+            .join("cache/SRR0000001-0123456789abcdef.89abcdef.tmp");
+        std::fs::write(tmp, "[").unwrap();
+        assert_eq!(
+            cache.stats().unwrap(),
+            CacheStats {
+                entries: 2,
+                expired: 0,
+                temporary: 1,
+                bytes: 5,
+            }
+        );
+        assert_eq!(cache.clear().unwrap(), 3);
+        assert_eq!(cache.stats().unwrap(), CacheStats::default());
+    }
+}
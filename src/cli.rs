@@ -0,0 +1,875 @@
+use crate::client::{parse_ena_url, FILE_FIELDS};
+use crate::filter::{parse_date, Date};
+use crate::{
+    normalise_accession, read_accessions, read_accessions_column, read_sample_names,
+    validate_accession, AccessionColumn, Accessions, Cache, Error, FileTypeFilter, OutputFormat,
+    ReportFormat, RetryPolicy, RunFilter, RunOrder, SampleFrom, SamplesheetOptions, ENA_URL,
+};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// CLI options and arguments
+#[derive(Parser, Debug)]
+#[clap(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Args {
+    #[clap(subcommand)]
+    /// Do something with the runs other than printing their download links
+    pub command: Option<Command>,
+
+    #[clap(flatten)]
+    /// The accessions to query, and how to query them
+    pub query: QueryArgs,
+
+    #[clap(
+        value_enum,
+        short = 'o',
+        long = "output-format",
+        value_name = "FORMAT",
+        default_value_t = OutputFormat::Json,
+        help = "Format for output of data."
+    )]
+    /// The output format for the download links
+    /// If this is specified, the data will be written to the output format
+    /// If this is not specified, the data will be written to stdout
+    pub format: OutputFormat,
+
+    #[clap(
+        long,
+        help = "Write the json format on a single line instead of pretty printing it"
+    )]
+    /// Write the json output format compactly, on a single line, rather than
+    /// indented over many lines
+    pub compact: bool,
+
+    #[clap(
+        value_enum,
+        long = "sample-from",
+        value_name = "FROM",
+        default_value_t = SampleFrom::Run,
+        help = "Accession to name the samples of a samplesheet after."
+    )]
+    /// Whether each run is a sample of its own in the samplesheet formats, or
+    /// the runs are grouped by their sample accession
+    pub sample_from: SampleFrom,
+
+    #[clap(
+        long = "sample-map",
+        value_name = "FILE",
+        help = "File with a run or sample accession and a sample name on each line"
+    )]
+    /// Names for the samples in the samplesheet formats, overriding
+    /// --sample-from for the accessions in the file
+    pub sample_map: Option<PathBuf>,
+
+    #[clap(
+        long = "local-dir",
+        value_name = "DIR",
+        help = "Directory the files are downloaded into, for samplesheets, scripts and manifests"
+    )]
+    /// The directory the files were downloaded into with the download
+    /// subcommand, for samplesheets that point at the local copies, or the
+    /// directory the download scripts and manifests write the files to
+    pub local_dir: Option<PathBuf>,
+}
+
+impl Args {
+    /// How to write samplesheets, as requested on the command line
+    pub fn samplesheet_options(&self) -> Result<SamplesheetOptions, Error> {
+        let sample_names = match &self.sample_map {
+            Some(path) => read_sample_names(path)?,
+            None => Default::default(),
+        };
+        Ok(SamplesheetOptions {
+            sample_from: self.sample_from,
+            sample_names,
+            local_dir: self.local_dir.clone(),
+        })
+    }
+}
+
+/// The subcommands, each of which queries ENA for the runs first
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Download the FASTQ files of the runs into a directory
+    Download(DownloadArgs),
+    /// Check the FASTQ files of the runs in a directory against their size and MD5
+    Verify(VerifyArgs),
+    /// Manage the cache of responses from the ENA API
+    Cache(CacheArgs),
+}
+
+/// Options and arguments for the download subcommand
+#[derive(clap::Args, Debug)]
+pub struct DownloadArgs {
+    #[clap(flatten)]
+    /// The accessions to download, and how to query them
+    pub query: QueryArgs,
+
+    #[clap(
+        short = 'd',
+        long = "output-dir",
+        value_name = "DIR",
+        default_value = ".",
+        help = "Directory to download the FASTQ files into"
+    )]
+    /// The directory the FASTQ files are written to, in a directory for each
+    /// run. It is created if it does not exist
+    pub output_dir: PathBuf,
+}
+
+/// Options and arguments for the verify subcommand
+#[derive(clap::Args, Debug)]
+pub struct VerifyArgs {
+    #[clap(flatten)]
+    /// The accessions to verify, and how to query them
+    pub query: QueryArgs,
+
+    #[clap(
+        short = 'd',
+        long = "dir",
+        value_name = "DIR",
+        default_value = ".",
+        help = "Directory containing the FASTQ files to verify"
+    )]
+    /// The directory the FASTQ files are in, where the download subcommand
+    /// puts them
+    pub dir: PathBuf,
+}
+
+/// Options and arguments for the cache subcommand
+#[derive(clap::Args, Debug)]
+pub struct CacheArgs {
+    #[clap(subcommand)]
+    /// What to do with the cache
+    pub action: CacheAction,
+
+    #[clap(flatten)]
+    /// Where the cache is, and how long responses are kept for
+    pub cache: CacheOptions,
+}
+
+/// The things that can be done with the cache
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheAction {
+    /// Remove all the responses from the cache
+    Clear,
+    /// Show the number and size of the responses in the cache
+    Stats,
+}
+
+/// Options for the cache of responses from the ENA API
+#[derive(clap::Args, Debug)]
+pub struct CacheOptions {
+    #[clap(
+        long = "cache-dir",
+        value_name = "DIR",
+        env = "PHCUE_CK_CACHE_DIR",
+        help = "Directory to cache responses from the ENA API in [default: the user cache directory]"
+    )]
+    /// The directory responses are cached in. By default, this is phcue-ck
+    /// in the user's cache directory (e.g., $XDG_CACHE_HOME on Linux)
+    pub cache_dir: Option<PathBuf>,
+
+    #[clap(
+        long = "cache-ttl",
+        value_name = "HOURS",
+        default_value = "24",
+        value_parser = parse_hours,
+        help = "Number of hours a cached response is used for before querying ENA again"
+    )]
+    /// How long a cached response is used for, given in hours
+    pub cache_ttl: Duration,
+}
+
+impl CacheOptions {
+    /// The cache requested on the command line, if there is a directory for it
+    pub fn cache(&self) -> Option<Cache> {
+        let dir = self.cache_dir.clone().or_else(Cache::default_dir)?;
+        Some(Cache::new(dir, self.cache_ttl))
+    }
+}
+
+/// Options and arguments shared by everything that queries ENA
+#[derive(clap::Args, Debug)]
+pub struct QueryArgs {
+    #[clap(short, long, value_parser = parse_accession, multiple = true, required_unless_present_any = &["file", "from-json"])]
+    /// The accession to query (a run, experiment, sample, study or project accession)
+    pub accession: Vec<String>,
+
+    #[clap(
+        short = 'n',
+        long = "num-requests",
+        value_name = "NUM",
+        default_value = "1",
+        help = "Maximum number of concurrent requests to make to the ENA API (max of 10 are allowed)"
+    )]
+    /// The maximum number of concurrent requests to make to the ENA API
+    /// Default: 1
+    /// Maximum: 10
+    /// Minimum: 1
+    pub num_requests: u8,
+
+    #[clap(
+        short,
+        long,
+        value_name = "FILE",
+        help = "File containing accessions to query, or - to read them from stdin",
+        required_unless_present_any = &["accession", "from-json"]
+    )]
+    /// The file containing accessions to query
+    /// If this is specified, the accessions will be read from this file
+    /// If this is not specified, the accessions will be read from the command line
+    /// If this is -, the accessions will be read from standard input
+    pub file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_parser,
+        value_name = "NAME|INDEX",
+        help = "Read the accessions from this column of a CSV or TSV --file",
+        requires = "file"
+    )]
+    /// The column of the file holding the accessions, by its name in the
+    /// header line or its position counting from 1. Without it, the file has
+    /// an accession on each line
+    pub column: Option<AccessionColumn>,
+
+    #[clap(
+        long,
+        value_parser = parse_delimiter,
+        value_name = "CHAR",
+        help = "Delimiter of the --column file [default: tab if the first line has one, else comma]",
+        requires = "column"
+    )]
+    /// The character separating the columns of the file, e.g. ',' or '\t'
+    pub delimiter: Option<u8>,
+
+    #[clap(
+        long = "from-json",
+        value_name = "FILE",
+        help = "Read the runs from JSON written by phcue-ck instead of querying ENA",
+        conflicts_with_all = &["accession", "file"]
+    )]
+    /// A file with the runs written out by phcue-ck in the JSON format. The
+    /// runs are read from this file, rather than querying ENA for them
+    pub from_json: Option<PathBuf>,
+
+    #[clap(
+        short,
+        long,
+        help = "Keep single end reads if there are paired end reads too"
+    )]
+    /// Keep single end reads if there are paired end reads too
+    /// By default, we discard single end reads if there are paired end reads too.
+    /// This is if the user does wish to have the single end reads
+    pub keep_single_end: bool,
+
+    #[clap(
+        value_enum,
+        long = "file-type",
+        value_name = "TYPE",
+        default_value_t = FileTypeFilter::Fastq,
+        help = "Type of file to output for each run."
+    )]
+    /// The type of file to output for each run: the FASTQ files generated by
+    /// ENA, the files as they were submitted (e.g., BAM or CRAM), the
+    /// SRA-format file, or all of them
+    pub file_type: FileTypeFilter,
+
+    #[clap(
+        value_enum,
+        long,
+        value_name = "ORDER",
+        default_value_t = RunOrder::Input,
+        help = "Order to output the runs in"
+    )]
+    /// The order to output the runs in: the order the accessions were given
+    /// in, sorted by run accession, or the order the queries finished in
+    pub order: RunOrder,
+
+    #[clap(
+        long,
+        value_name = "FIELDS",
+        value_delimiter = ',',
+        value_parser = parse_field,
+        help = "Comma separated list of ENA metadata fields to output for each run"
+    )]
+    /// Extra fields to request from the ENA API for each run, such as
+    /// scientific_name or read_count. They are added to the JSON output under
+    /// metadata, and as extra columns or variables to the csv formats. The
+    /// fields describing the files of a run, such as fastq_ftp, are not
+    /// metadata, and are rejected
+    pub fields: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "PLATFORM",
+        help = "Only output runs sequenced on this platform (e.g., ILLUMINA)"
+    )]
+    /// Drop the runs whose instrument_platform in ENA is not this one
+    pub platform: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "LAYOUT",
+        help = "Only output runs with this library layout (SINGLE or PAIRED)"
+    )]
+    /// Drop the runs whose library_layout in ENA is not this one
+    pub layout: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "STRATEGY",
+        help = "Only output runs with this library strategy (e.g., WGS)"
+    )]
+    /// Drop the runs whose library_strategy in ENA is not this one
+    pub strategy: Option<String>,
+
+    #[clap(
+        long = "min-bases",
+        value_name = "NUM",
+        help = "Only output runs with at least this many bases"
+    )]
+    /// Drop the runs whose base_count in ENA is lower than this
+    pub min_bases: Option<u64>,
+
+    #[clap(
+        long = "max-bytes",
+        value_name = "NUM",
+        help = "Only output runs whose files add up to at most this many bytes"
+    )]
+    /// Drop the runs whose selected files are larger than this in total
+    pub max_bytes: Option<u64>,
+
+    #[clap(
+        long = "published-after",
+        value_name = "DATE",
+        value_parser = parse_date,
+        help = "Only output runs first made public on or after this date (YYYY-MM-DD)"
+    )]
+    /// Drop the runs whose first_public date in ENA is before this one
+    pub published_after: Option<Date>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Write the outcome of the query for each accession to this file"
+    )]
+    /// A sidecar file reporting whether runs were found, not found, or the
+    /// query failed for each of the accessions
+    pub report: Option<PathBuf>,
+
+    #[clap(
+        value_enum,
+        long = "report-format",
+        value_name = "FORMAT",
+        default_value_t = ReportFormat::Tsv,
+        help = "Format of the report file."
+    )]
+    /// The format of the report file
+    pub report_format: ReportFormat,
+
+    #[clap(
+        long = "max-retries",
+        value_name = "NUM",
+        default_value = "3",
+        help = "Maximum number of times to retry a failed request to the ENA API"
+    )]
+    /// The number of times a request to the ENA API is retried if it fails
+    /// with an error that may go away (connection errors, 5xx and 429)
+    pub max_retries: u32,
+
+    #[clap(
+        long = "retry-base-delay",
+        value_name = "SECONDS",
+        default_value = "1",
+        value_parser = parse_seconds,
+        help = "Delay before the first retry, doubled for every retry after that"
+    )]
+    /// The delay before the first retry, given in seconds. A Retry-After
+    /// header sent by the ENA API takes precedence over this
+    pub retry_base_delay: Duration,
+
+    #[clap(
+        long = "ena-url",
+        value_name = "URL",
+        env = "PHCUE_CK_ENA_URL",
+        default_value = ENA_URL,
+        value_parser = parse_ena_url,
+        help = "Base URL of the ENA portal API, or of a proxy or mirror of it"
+    )]
+    /// The base URL the ENA API is queried at, e.g., to go through a proxy,
+    /// use a local mirror, or stand in for ENA in tests
+    pub ena_url: String,
+
+    #[clap(
+        long = "ena-endpoint",
+        value_name = "PATH",
+        env = "PHCUE_CK_ENA_ENDPOINT",
+        default_value = "filereport",
+        help = "Endpoint of the ENA portal API that reports the files of runs"
+    )]
+    /// The path of the endpoint under the base URL
+    pub ena_endpoint: String,
+
+    #[clap(
+        long = "ena-result",
+        value_name = "RESULT",
+        env = "PHCUE_CK_ENA_RESULT",
+        default_value = "read_run",
+        help = "Type of result to ask the ENA portal API for"
+    )]
+    /// The result= parameter of the request
+    pub ena_result: String,
+
+    #[clap(flatten)]
+    /// Where responses from the ENA API are cached, and for how long
+    pub cache: CacheOptions,
+
+    #[clap(
+        long = "no-cache",
+        help = "Always query ENA, without using or filling the cache"
+    )]
+    /// Do not read or write the cache of responses from the ENA API
+    pub no_cache: bool,
+
+    #[clap(
+        long,
+        conflicts_with = "no-cache",
+        help = "Query ENA even for responses that are in the cache, and update the cache"
+    )]
+    /// Ignore the responses in the cache, replacing them with fresh ones
+    pub refresh: bool,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "ENA filereport saved as TSV or JSON to resolve accessions from with --offline, or when ENA cannot be queried"
+    )]
+    /// A dump of the ENA filereport for the runs, e.g., saved on a machine
+    /// with network access. Offline, accessions are resolved from it first;
+    /// otherwise it is only used for accessions that ENA cannot be queried
+    /// for
+    pub filereport: Option<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with = "refresh",
+        help = "Never query ENA, resolving accessions only from the filereport and the cache"
+    )]
+    /// Resolve the accessions only from the filereport and the cache, using
+    /// cached responses however old they are. Accessions that are in
+    /// neither fail
+    pub offline: bool,
+}
+
+impl QueryArgs {
+    /// The accessions to query, either from the file (or a column of it) or
+    /// the command line
+    pub fn accessions(&self) -> Result<Vec<String>, Error> {
+        match &self.file {
+            Some(file) => match &self.column {
+                Some(column) => read_accessions_column(file, column, self.delimiter),
+                None => read_accessions(file),
+            },
+            None => {
+                let accessions = Accessions::from_values(&self.accession);
+                accessions.warn();
+                Ok(accessions.accessions)
+            }
+        }
+    }
+
+    /// The filter requested on the command line, if any
+    pub fn run_filter(&self) -> Option<RunFilter> {
+        let filters = [
+            self.platform.clone().map(RunFilter::Platform),
+            self.layout.clone().map(RunFilter::Layout),
+            self.strategy.clone().map(RunFilter::Strategy),
+            self.min_bases.map(RunFilter::MinBases),
+            self.max_bytes.map(RunFilter::MaxBytes),
+            self.published_after.map(RunFilter::PublishedAfter),
+        ];
+        filters.into_iter().flatten().reduce(RunFilter::and)
+    }
+
+    /// The cache requested on the command line, unless caching is turned off
+    pub fn cache(&self) -> Option<Cache> {
+        if self.no_cache {
+            return None;
+        }
+        let mut cache = self.cache.cache()?;
+        cache.refresh = self.refresh;
+        Some(cache)
+    }
+
+    /// The retry policy requested on the command line
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.max_retries,
+            base_delay: self.retry_base_delay,
+            ..RetryPolicy::default()
+        }
+    }
+}
+
+/// Parse an accession given on the command line, normalising it as in a file
+fn parse_accession(accession: &str) -> Result<String, String> {
+    let normalised = normalise_accession(accession).unwrap_or_default();
+    validate_accession(&normalised).map_err(|e| e.to_string())?;
+    Ok(normalised)
+}
+
+/// Parse the name of a metadata field, which cannot be one of the fields
+/// describing the files, as those are always requested and written out as
+/// files rather than metadata
+fn parse_field(field: &str) -> Result<String, String> {
+    if FILE_FIELDS.contains(&field) {
+        return Err(format!(
+            "{} describes the files of a run, which are always written out; use --file-type to choose which files",
+            field
+        ));
+    }
+    Ok(field.to_string())
+}
+
+/// Parse a length of time given as a number of units of this many seconds,
+/// which has to be a number of seconds that a Duration can hold
+fn parse_duration(value: &str, unit_secs: f64) -> Result<Duration, String> {
+    let number = value
+        .parse::<f64>()
+        .map_err(|_| format!("{:?} is not a number", value))?;
+    if number < 0.0 {
+        return Err(format!("{} is negative", value));
+    }
+    Duration::try_from_secs_f64(number * unit_secs).map_err(|_| format!("{} is too long", value))
+}
+
+/// Parse a length of time given in seconds
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    parse_duration(seconds, 1.0)
+}
+
+/// Parse a length of time given in hours
+fn parse_hours(hours: &str) -> Result<Duration, String> {
+    parse_duration(hours, 3600.0)
+}
+
+/// Parse a delimiter given as a single character, or as `\t` or `tab` for a
+/// tab, which is awkward to type in a shell
+fn parse_delimiter(delimiter: &str) -> Result<u8, String> {
+    match delimiter {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if delimiter.len() == 1 && delimiter.is_ascii() => Ok(delimiter.as_bytes()[0]),
+        _ => Err(format!("{:?} is not a single character", delimiter)),
+    }
+}
+
+pub fn parse_args() -> Args {
+    Args::parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_parse_query() {
+        let args = Args::try_parse_from(["phcue-ck", "-a", "SRR1234567", "-o", "csv"]).unwrap();
+        assert!(args.command.is_none());
+        assert_eq!(args.query.accession, vec!["SRR1234567"]);
+        assert_eq!(args.query.file_type, FileTypeFilter::Fastq);
+        let args =
+            Args::try_parse_from(["phcue-ck", "-a", "SRR1234567", "--file-type", "all"]).unwrap();
+        assert_eq!(args.query.file_type, FileTypeFilter::All);
+        let args = Args::try_parse_from([
+            "phcue-ck",
+            "-a",
+            "SRR1234567",
+            "--fields",
+            "scientific_name,read_count",
+        ])
+        .unwrap();
+        assert_eq!(args.query.fields, vec!["scientific_name", "read_count"]);
+        assert_eq!(args.query.run_filter(), None);
+        for fields in ["fastq_ftp", "tax_id,submitted_md5", "run_accession"] {
+            let args = ["phcue-ck", "-a", "SRR1234567", "--fields", fields];
+            assert!(Args::try_parse_from(args).is_err(), "{}", fields);
+        }
+        assert!(!args.compact);
+        let args =
+            Args::try_parse_from(["phcue-ck", "-a", "SRR1234567", "-o", "jsonl", "--compact"])
+                .unwrap();
+        assert!(matches!(args.format, OutputFormat::Jsonl));
+        assert!(args.compact);
+    }
+
+    #[test]
+    fn test_parse_retry() {
+        let args = Args::try_parse_from([
+            "phcue-ck",
+            "-a",
+            "SRR1234567",
+            "--max-retries",
+            "5",
+            "--retry-base-delay",
+            "0.25",
+        ])
+        .unwrap();
+        let policy = args.query.retry_policy();
+        assert_eq!(policy.max_retries, 5);
+        assert_eq!(policy.base_delay, Duration::from_millis(250));
+        for delay in ["inf", "1e300", "NaN", "-1"] {
+            let args = ["phcue-ck", "-a", "SRR1234567", "--retry-base-delay", delay];
+            assert!(Args::try_parse_from(args).is_err(), "{}", delay);
+        }
+    }
+
+    #[test]
+    fn test_parse_accessions() {
+        let args = Args::try_parse_from([
+            "phcue-ck",
+            "-a",
+            " srr1234567",
+            "-a",
+            "ERR1234567",
+            "-a",
+            "SRR1234567",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.query.accessions().unwrap(),
+            vec!["SRR1234567", "ERR1234567"]
+        );
+        assert!(Args::try_parse_from(["phcue-ck", "-a", "1234567"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_column() {
+        let args = Args::try_parse_from([
+            "phcue-ck",
+            "--file",
+            "-",
+            "--column",
+            "run_accession",
+            "--delimiter",
+            "\\t",
+        ])
+        .unwrap();
+        assert_eq!(args.query.file, Some(PathBuf::from("-")));
+        assert_eq!(
+            args.query.column,
+            Some(AccessionColumn::Name("run_accession".to_string()))
+        );
+        assert_eq!(args.query.delimiter, Some(b'\t'));
+        let args = Args::try_parse_from(["phcue-ck", "-f", "sheet.csv", "--column", "2"]).unwrap();
+        assert_eq!(args.query.column, Some(AccessionColumn::Index(2)));
+        assert_eq!(args.query.delimiter, None);
+        assert!(Args::try_parse_from(["phcue-ck", "-a", "SRR1234567", "--column", "2"]).is_err());
+        assert!(Args::try_parse_from([
+            "phcue-ck",
+            "-f",
+            "x",
+            "--column",
+            "1",
+            "--delimiter",
+            ";;"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_samplesheet() {
+        let args = Args::try_parse_from([
+            "phcue-ck",
+            "-a",
+            "SRR1234567",
+            "-o",
+            "samplesheet",
+            "--sample-from",
+            "sample",
+            "--local-dir",
+            "reads",
+        ])
+        .unwrap();
+        let options = args.samplesheet_options().unwrap();
+        assert_eq!(options.sample_from, SampleFrom::Sample);
+        assert!(options.sample_names.is_empty());
+        assert_eq!(options.local_dir, Some(PathBuf::from("reads")));
+    }
+
+    #[test]
+    fn test_parse_sample_map_with_fields() {
+        // Asking for other fields replaces the default ENA fields, which
+        // have the sample accessions the sample map is keyed on
+        let mut map = tempfile::NamedTempFile::new().unwrap();
+        writeln!(map, "SAMN0000001,first").unwrap();
+        let path = map.path().display().to_string();
+        let args = Args::try_parse_from([
+            "phcue-ck",
+            "-a",
+            "SRR1234567",
+            "-o",
+            "samplesheet",
+            "--sample-from",
+            "run",
+            "--sample-map",
+            &path,
+            "--fields",
+            "tax_id",
+        ])
+        .unwrap();
+        let options = args.samplesheet_options().unwrap();
+        assert_eq!(options.sample_from, SampleFrom::Run);
+        assert_eq!(options.fields(), vec!["sample_accession"]);
+    }
+
+    #[test]
+    fn test_parse_filters() {
+        let args = Args::try_parse_from([
+            "phcue-ck",
+            "-a",
+            "SRR1234567",
+            "--platform",
+            "ILLUMINA",
+            "--max-bytes",
+            "1000",
+            "--published-after",
+            "2021-01-01",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.query.run_filter(),
+            Some(RunFilter::All(vec![
+                RunFilter::Platform("ILLUMINA".to_string()),
+                RunFilter::MaxBytes(1000),
+                RunFilter::PublishedAfter(Date::new(2021, 1, 1).unwrap()),
+            ]))
+        );
+        assert!(Args::try_parse_from([
+            "phcue-ck",
+            "-a",
+            "SRR1234567",
+            "--published-after",
+            "1/1/2021"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_download() {
+        let args = Args::try_parse_from(["phcue-ck", "download", "-a", "SRR1234567", "-d", "out"])
+            .unwrap();
+        match args.command {
+            Some(Command::Download(download)) => {
+                assert_eq!(download.query.accession, vec!["SRR1234567"]);
+                assert_eq!(download.output_dir, PathBuf::from("out"));
+            }
+            _ => panic!("expected the download subcommand"),
+        }
+    }
+
+    #[test]
+    fn test_parse_verify_from_json() {
+        let args = Args::try_parse_from([
+            "phcue-ck",
+            "verify",
+            "--from-json",
+            "runs.json",
+            "-d",
+            "out",
+        ])
+        .unwrap();
+        match args.command {
+            Some(Command::Verify(verify)) => {
+                assert_eq!(verify.query.from_json, Some(PathBuf::from("runs.json")));
+                assert_eq!(verify.dir, PathBuf::from("out"));
+            }
+            _ => panic!("expected the verify subcommand"),
+        }
+        assert!(
+            Args::try_parse_from(["phcue-ck", "--from-json", "runs.json", "-a", "SRR1234567"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_ena_url() {
+        let args = Args::try_parse_from(["phcue-ck", "-a", "SRR1234567"]).unwrap();
+        assert_eq!(args.query.ena_url, ENA_URL);
+        assert_eq!(args.query.ena_endpoint, "filereport");
+        assert_eq!(args.query.ena_result, "read_run");
+        let args = Args::try_parse_from([
+            "phcue-ck",
+            "-a",
+            "SRR1234567",
+            "--ena-url",
+            "http://localhost:8080",
+        ])
+        .unwrap();
+        assert_eq!(args.query.ena_url, "http://localhost:8080");
+        assert!(
+            Args::try_parse_from(["phcue-ck", "-a", "SRR1234567", "--ena-url", "localhost"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_cache() {
+        let args = Args::try_parse_from([
+            "phcue-ck",
+            "-a",
+            "SRR1234567",
+            "--cache-dir",
+            "cache",
+            "--cache-ttl",
+            "0.5",
+            "--refresh",
+        ])
+        .unwrap();
+        let cache = args.query.cache().unwrap();
+        assert_eq!(cache.dir, PathBuf::from("cache"));
+        assert_eq!(cache.ttl, Duration::from_secs(1800));
+        assert!(cache.refresh);
+        for ttl in ["inf", "1e300", "NaN", "-1", "a day"] {
+            let args = ["phcue-ck", "-a", "SRR1234567", "--cache-ttl", ttl];
+            assert!(Args::try_parse_from(args).is_err(), "{}", ttl);
+            let args = ["phcue-ck", "cache", "--cache-ttl", ttl, "stats"];
+            assert!(Args::try_parse_from(args).is_err(), "{}", ttl);
+        }
+        let args = Args::try_parse_from(["phcue-ck", "-a", "SRR1234567", "--no-cache"]).unwrap();
+        assert!(args.query.cache().is_none());
+        assert!(
+            Args::try_parse_from(["phcue-ck", "-a", "SRR1234567", "--offline", "--refresh"])
+                .is_err()
+        );
+        assert!(
+            Args::try_parse_from(["phcue-ck", "-a", "SRR1234567", "--no-cache", "--refresh"])
+                .is_err()
+        );
+        let args =
+            Args::try_parse_from(["phcue-ck", "cache", "--cache-dir", "cache", "stats"]).unwrap();
+        match args.command {
+            Some(Command::Cache(cache)) => {
+                assert_eq!(cache.action, CacheAction::Stats);
+                assert_eq!(cache.cache.cache_dir, Some(PathBuf::from("cache")));
+            }
+            _ => panic!("expected the cache subcommand"),
+        }
+    }
+
+    #[test]
+    fn test_parse_requires_accessions() {
+        assert!(Args::try_parse_from(["phcue-ck"]).is_err());
+        assert!(Args::try_parse_from(["phcue-ck", "download"]).is_err());
+    }
+}
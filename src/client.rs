@@ -0,0 +1,1022 @@
+use crate::retry::{is_retryable_error, is_retryable_status};
+use crate::{
+    validate_accession, AccessionOutcome, AccessionType, Cache, ENAApiResponse, Error, Filereport,
+    QueryOutcome, QueryReport, RetryPolicy, Run,
+};
+use futures::{Future, Stream, StreamExt};
+use std::time::Duration;
+
+/// The fields describing the files of a run, which are always requested
+pub(crate) const FILE_FIELDS: [&str; 10] = [
+    "run_accession",
+    "fastq_ftp",
+    "fastq_bytes",
+    "fastq_md5",
+    "submitted_ftp",
+    "submitted_bytes",
+    "submitted_md5",
+    "sra_ftp",
+    "sra_bytes",
+    "sra_md5",
+];
+
+/// The base URL of the ENA portal API
+pub const ENA_URL: &str = "https://www.ebi.ac.uk/ena/portal/api";
+
+/// The user agent requests to the ENA API are made with, by default
+pub(crate) const USER_AGENT: &str = concat!("phcue-ck/", env!("CARGO_PKG_VERSION"));
+
+/// A client for resolving accessions into runs using the ENA API. It holds
+/// on to its connections, so a service should build one client with
+/// [`EnaClient::builder`] and use it for all of its queries. Cloning the
+/// client is cheap, and the clones share their connections
+#[derive(Debug, Clone)]
+pub struct EnaClient {
+    /// The base URL of the ENA portal API, which can be pointed at a proxy,
+    /// a mirror or a stand-in for ENA in tests
+    pub base_url: String,
+    /// The endpoint of the API that reports the files of runs
+    pub endpoint: String,
+    /// The type of result to ask the endpoint for
+    pub result: String,
+    /// How failed requests are retried
+    pub retry: RetryPolicy,
+    /// The maximum number of concurrent requests to make to the ENA API.
+    /// Fewer than one is taken as one
+    pub num_requests: usize,
+    /// The metadata fields to request for each run, on top of the fields
+    /// describing its files
+    pub fields: Vec<String>,
+    /// Where responses are cached between queries, if they are cached
+    pub cache: Option<Cache>,
+    /// A dump of the ENA filereport to resolve accessions from. Offline, it
+    /// is used before the cache; otherwise, only for the accessions that ENA
+    /// could not be queried for
+    pub filereport: Option<Filereport>,
+    /// Never query the ENA API, resolving accessions only from the
+    /// filereport and the cache, however old the cached responses are
+    pub offline: bool,
+    client: reqwest::Client,
+}
+
+impl Default for EnaClient {
+    fn default() -> Self {
+        EnaClientBuilder::default()
+            .build()
+            .expect("the default client settings are valid")
+    }
+}
+
+/// Builds an [`EnaClient`], setting up its HTTP client with the timeouts,
+/// user agent and proxy to use for every request
+#[derive(Debug, Clone)]
+pub struct EnaClientBuilder {
+    base_url: String,
+    endpoint: String,
+    result: String,
+    retry: RetryPolicy,
+    num_requests: usize,
+    fields: Vec<String>,
+    cache: Option<Cache>,
+    filereport: Option<Filereport>,
+    offline: bool,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    user_agent: String,
+    proxy: Option<String>,
+}
+
+impl Default for EnaClientBuilder {
+    fn default() -> Self {
+        Self {
+            base_url: ENA_URL.to_string(),
+            endpoint: "filereport".to_string(),
+            result: "read_run".to_string(),
+            retry: RetryPolicy::default(),
+            num_requests: 1,
+            fields: Vec::new(),
+            cache: None,
+            filereport: None,
+            offline: false,
+            timeout: Some(Duration::from_secs(300)),
+            connect_timeout: Some(Duration::from_secs(30)),
+            user_agent: USER_AGENT.to_string(),
+            proxy: None,
+        }
+    }
+}
+
+impl EnaClientBuilder {
+    /// The base URL of the ENA portal API
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// The endpoint of the API that reports the files of runs
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// The type of result to ask the endpoint for
+    pub fn result(mut self, result: impl Into<String>) -> Self {
+        self.result = result.into();
+        self
+    }
+
+    /// How failed requests are retried
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// The maximum number of concurrent requests, which is kept between 1
+    /// and 10 to be nice to ENA
+    pub fn num_requests(mut self, num_requests: usize) -> Self {
+        self.num_requests = num_requests.clamp(1, 10);
+        self
+    }
+
+    /// The metadata fields to request for each run
+    pub fn fields(mut self, fields: Vec<String>) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Where to cache responses between queries
+    pub fn cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// A dump of the ENA filereport to resolve accessions from when offline,
+    /// or when ENA cannot be queried
+    pub fn filereport(mut self, filereport: Option<Filereport>) -> Self {
+        self.filereport = filereport;
+        self
+    }
+
+    /// Never query the ENA API, only the filereport and the cache
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// How long a request, including reading the response, may take before
+    /// it fails, or None to wait for as long as it takes. Five minutes by
+    /// default, as a large project can take a while for ENA to list
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long connecting to the ENA API may take before the request
+    /// fails, or None to wait for as long as it takes. 30 seconds by default
+    pub fn connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// The user agent to make requests with, by default phcue-ck and its
+    /// version
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// A proxy to send all requests through. Without one, the proxy in the
+    /// HTTP_PROXY and HTTPS_PROXY environment variables is used, if any
+    pub fn proxy(mut self, proxy: Option<String>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Build the client, failing if the proxy is not a valid URL or the HTTP
+    /// client cannot be set up
+    pub fn build(self) -> Result<EnaClient, Error> {
+        let mut client = reqwest::Client::builder().user_agent(self.user_agent);
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(timeout);
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            client = client.connect_timeout(connect_timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            client = client.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        Ok(EnaClient {
+            base_url: self.base_url,
+            endpoint: self.endpoint,
+            result: self.result,
+            retry: self.retry,
+            num_requests: self.num_requests,
+            fields: self.fields,
+            cache: self.cache,
+            filereport: self.filereport,
+            offline: self.offline,
+            client: client.build()?,
+        })
+    }
+}
+
+impl EnaClient {
+    /// A client with the default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start building a client with settings other than the defaults
+    pub fn builder() -> EnaClientBuilder {
+        EnaClientBuilder::default()
+    }
+
+    /// Query the ENA API and return a vector of Run instances
+    /// If the accession is a study, project, sample or experiment, ENA expands it
+    /// into all of its runs, and the accession is recorded as the parent of each run.
+    /// An accession that is not one ENA can expand into runs is rejected with
+    /// [`Error::InvalidAccession`] before anything is sent
+    pub async fn resolve_one(&self, accession: &str) -> Result<Vec<Run>, Error> {
+        validate_accession(accession)?;
+        let request_url = self.request_url(accession)?;
+        let request_url = request_url.as_str();
+        let lookup = || self.filereport.as_ref().and_then(|f| f.lookup(accession));
+        let body = if self.offline {
+            match lookup() {
+                Some(body) => body,
+                None => self.get_cached(accession, request_url).await?,
+            }
+        } else {
+            // ENA is the source of truth, and the filereport only stands in
+            // for it when it cannot be reached
+            match self.get_cached(accession, request_url).await {
+                Ok(body) => body,
+                Err(e) => lookup().ok_or(e)?,
+            }
+        };
+        // ENA returns an empty body rather than an empty array when nothing matches
+        if body.trim().is_empty() {
+            return Err(Error::NotFound(accession.to_owned()));
+        }
+        let responses: Vec<ENAApiResponse> =
+            serde_json::from_str(&body).map_err(|e| Error::MalformedResponse {
+                accession: accession.to_owned(),
+                message: e.to_string(),
+            })?;
+        let mut runs = responses
+            .into_iter()
+            .map(Run::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        if runs.is_empty() {
+            return Err(Error::NotFound(accession.to_owned()));
+        }
+        // ENA may return fields that were not asked for
+        runs.iter_mut()
+            .for_each(|run| run.metadata.retain(&self.fields));
+        if AccessionType::from_accession(accession).is_some_and(|t| t.is_container()) {
+            runs.iter_mut()
+                .for_each(|run| run.parent_accession = Some(accession.to_owned()));
+        }
+        Ok(runs)
+    }
+
+    /// The URL of the query for the accession, with each parameter encoded
+    /// so that none of them can change the rest of the query
+    fn request_url(&self, accession: &str) -> Result<reqwest::Url, Error> {
+        let url = format!(
+            "{}/{}",
+            self.base_url.trim_end_matches('/'),
+            self.endpoint.trim_matches('/')
+        );
+        let mut url = reqwest::Url::parse(&url).map_err(|e| Error::InvalidUrl {
+            url: url.clone(),
+            message: e.to_string(),
+        })?;
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("accession", accession)
+                .append_pair("result", &self.result)
+                .append_pair("format", "json");
+            // Without a list of fields ENA returns its default set, which has
+            // everything describing the files
+            if !self.fields.is_empty() {
+                let fields = FILE_FIELDS
+                    .iter()
+                    .copied()
+                    .chain(
+                        self.fields
+                            .iter()
+                            .map(String::as_str)
+                            .filter(|field| !FILE_FIELDS.contains(field)),
+                    )
+                    .collect::<Vec<_>>();
+                query.append_pair("fields", &fields.join(","));
+            }
+        }
+        Ok(url)
+    }
+
+    /// Query the ENA API concurrently across multiple accessions
+    /// Every accession is reported, in the order it was requested, along with
+    /// the runs that were found for it, or the reason why none were returned
+    pub async fn resolve(&self, accessions: Vec<String>) -> QueryReport {
+        QueryReport {
+            outcomes: self.resolve_stream_in_order(accessions).collect().await,
+        }
+    }
+
+    /// Query the ENA API concurrently across multiple accessions, yielding
+    /// the outcome for each accession as soon as it is known, so in the
+    /// order the queries finish rather than the order of the accessions
+    pub fn resolve_stream<'a, I>(
+        &'a self,
+        accessions: I,
+    ) -> impl Stream<Item = AccessionOutcome> + 'a
+    where
+        I: IntoIterator<Item = String>,
+        I::IntoIter: 'a,
+    {
+        self.queries(accessions)
+            .buffer_unordered(self.concurrency())
+    }
+
+    /// Query the ENA API concurrently across multiple accessions, yielding
+    /// the outcome for each accession in the order the accessions were
+    /// requested, as soon as it and the ones before it are known
+    pub fn resolve_stream_in_order<'a, I>(
+        &'a self,
+        accessions: I,
+    ) -> impl Stream<Item = AccessionOutcome> + 'a
+    where
+        I: IntoIterator<Item = String>,
+        I::IntoIter: 'a,
+    {
+        self.queries(accessions).buffered(self.concurrency())
+    }
+
+    /// Query the ENA API concurrently across multiple accessions, yielding
+    /// each run as soon as it and the runs for the accessions before it are
+    /// found. An accession without runs yields an [`Error::NotFound`], and
+    /// an accession whose query failed yields the error it failed with
+    pub fn resolve_runs<'a, I>(
+        &'a self,
+        accessions: I,
+    ) -> impl Stream<Item = Result<Run, Error>> + 'a
+    where
+        I: IntoIterator<Item = String>,
+        I::IntoIter: 'a,
+    {
+        self.resolve_stream_in_order(accessions).flat_map(
+            |AccessionOutcome { accession, outcome }| {
+                let results = match outcome {
+                    QueryOutcome::Found(runs) => runs.into_iter().map(Ok).collect(),
+                    QueryOutcome::NotFound => vec![Err(Error::NotFound(accession))],
+                    QueryOutcome::Failed(e) => vec![Err(e)],
+                };
+                futures::stream::iter(results)
+            },
+        )
+    }
+
+    /// A query of the ENA API for each accession, to be run as a stream
+    fn queries<'a, I>(
+        &'a self,
+        accessions: I,
+    ) -> impl Stream<Item = impl Future<Output = AccessionOutcome> + 'a> + 'a
+    where
+        I: IntoIterator<Item = String>,
+        I::IntoIter: 'a,
+    {
+        futures::stream::iter(accessions.into_iter().map(move |accession| {
+            log::info!("Querying ENA for accession: {}", accession);
+            async move {
+                let outcome = QueryOutcome::from(self.resolve_one(&accession).await);
+                AccessionOutcome { accession, outcome }
+            }
+        }))
+    }
+
+    /// Fetch the body of a response from the cache if it is there, or from
+    /// the ENA API otherwise, keeping it in the cache for next time. Empty
+    /// responses are not cached, so accessions that are not public yet are
+    /// looked up again. Offline, only the cache is used
+    async fn get_cached(&self, accession: &str, url: &str) -> Result<String, Error> {
+        let source = self.source();
+        if self.offline {
+            let body = match &self.cache {
+                Some(cache) => cache.get_stale(accession, &source, &self.fields).await,
+                None => None,
+            };
+            return body.ok_or_else(|| Error::NotAvailableOffline(accession.to_owned()));
+        }
+        let Some(cache) = &self.cache else {
+            return self.get_text(accession, url).await;
+        };
+        if let Some(body) = cache.get(accession, &source, &self.fields).await {
+            return Ok(body);
+        }
+        let body = self.get_text(accession, url).await?;
+        if !body.trim().is_empty() {
+            if let Err(e) = cache.put(accession, &source, &self.fields, &body).await {
+                log::warn!(
+                    "Could not cache the response for accession {}: {}",
+                    accession,
+                    e
+                );
+            }
+        }
+        Ok(body)
+    }
+
+    /// The number of queries to run at once. Streams that run no queries at
+    /// once never finish, so at least one is run even if `num_requests` has
+    /// been set to 0
+    fn concurrency(&self) -> usize {
+        self.num_requests.max(1)
+    }
+
+    /// The API the responses come from, which the cache keeps them apart by
+    fn source(&self) -> String {
+        format!(
+            "{}/{}?result={}",
+            self.base_url.trim_end_matches('/'),
+            self.endpoint.trim_matches('/'),
+            self.result
+        )
+    }
+
+    /// Fetch the body of a response from the ENA API, retrying according to
+    /// the retry policy if the request fails with an error that may go away
+    async fn get_text(&self, accession: &str, url: &str) -> Result<String, Error> {
+        let mut retry = 0;
+        loop {
+            let (error, delay) = match self.client.get(url).send().await {
+                Ok(response) if is_retryable_status(response.status()) => {
+                    let delay = self.retry.delay(retry, Some(&response));
+                    (response.error_for_status().unwrap_err(), delay)
+                }
+                Ok(response) => match response.error_for_status()?.text().await {
+                    Ok(body) => return Ok(body),
+                    Err(e) => (e, self.retry.delay(retry, None)),
+                },
+                Err(e) => (e, self.retry.delay(retry, None)),
+            };
+            if retry >= self.retry.max_retries || !is_retryable_error(&error) {
+                return Err(error.into());
+            }
+            retry += 1;
+            log::warn!(
+                "Request to ENA for accession {} failed, retrying in {:.1}s ({}/{}): {}",
+                accession,
+                delay.as_secs_f64(),
+                retry,
+                self.retry.max_retries,
+                error
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Check the base URL of the ENA API is an HTTP(S) URL
+pub(crate) fn parse_ena_url(url: &str) -> Result<String, String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(url.to_string()),
+        Ok(parsed) => Err(format!("unsupported scheme {}", parsed.scheme())),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve_offline() {
+        let filereport = Filereport::parse(
+            "run_accession\tstudy_accession\tfastq_ftp\tfastq_bytes\tfastq_md5\t\
+             submitted_ftp\tsubmitted_bytes\tsubmitted_md5\tsra_ftp\tsra_bytes\tsra_md5\n\
+             SRR0000001\tPRJNA000001\tftp.sra.ebi.ac.uk/SRR0000001.fastq.gz\t123\tmd5\t\t\t\t\t\t\n",
+        )
+        .unwrap();
+        let client = EnaClient {
+            filereport: Some(filereport),
+            offline: true,
+            ..EnaClient::default()
+        };
+        let runs = client.resolve_one("PRJNA000001").await.unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].accession, "SRR0000001");
+        assert_eq!(runs[0].parent_accession.as_deref(), Some("PRJNA000001"));
+        assert!(matches!(
+            client.resolve_one("SRR0000002").await,
+            Err(Error::NotAvailableOffline(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_filereport_online() {
+        let mut server = mockito::Server::new_async().await;
+        let found = server
+            .mock("GET", "/filereport")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "accession".into(),
+                "SRR0000001".into(),
+            ))
+            .with_body(
+                r#"[{"run_accession":"SRR0000001","fastq_ftp":"ftp.sra.ebi.ac.uk/ena.fastq.gz",
+                "fastq_bytes":"1","fastq_md5":"md5","submitted_ftp":"","submitted_bytes":"",
+                "submitted_md5":"","sra_ftp":"","sra_bytes":"","sra_md5":""}]"#,
+            )
+            .create_async()
+            .await;
+        let failed = server
+            .mock("GET", "/filereport")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "accession".into(),
+                "SRR0000002".into(),
+            ))
+            .with_status(500)
+            .create_async()
+            .await;
+        let filereport = Filereport::parse(
+            "run_accession\tfastq_ftp\tfastq_bytes\tfastq_md5\t\
+             submitted_ftp\tsubmitted_bytes\tsubmitted_md5\tsra_ftp\tsra_bytes\tsra_md5\n\
+             SRR0000001\tftp.sra.ebi.ac.uk/dump.fastq.gz\t1\tmd5\t\t\t\t\t\t\n\
+             SRR0000002\tftp.sra.ebi.ac.uk/dump.fastq.gz\t1\tmd5\t\t\t\t\t\t\n",
+        )
+        .unwrap();
+        let client = EnaClient {
+            filereport: Some(filereport),
+            ..retrying_client(&server, 0)
+        };
+        // ENA is asked first, and the filereport is only used when it fails
+        let runs = client.resolve_one("SRR0000001").await.unwrap();
+        assert_eq!(runs[0].reads[0].url, "ftp://ftp.sra.ebi.ac.uk/ena.fastq.gz");
+        let runs = client.resolve_one("SRR0000002").await.unwrap();
+        assert_eq!(
+            runs[0].reads[0].url,
+            "ftp://ftp.sra.ebi.ac.uk/dump.fastq.gz"
+        );
+        found.assert_async().await;
+        failed.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_resolve_from_base_url() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/mirror/ena/filereport")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("accession".into(), "SRR0000001".into()),
+                mockito::Matcher::UrlEncoded("result".into(), "read_run".into()),
+            ]))
+            .with_body(
+                r#"[{"run_accession":"SRR0000001","fastq_ftp":"","fastq_bytes":"","fastq_md5":"",
+                "submitted_ftp":"","submitted_bytes":"","submitted_md5":"",
+                "sra_ftp":"","sra_bytes":"","sra_md5":""}]"#,
+            )
+            .create_async()
+            .await;
+        let client = EnaClient {
+            base_url: format!("{}/mirror/", server.url()),
+            endpoint: "/ena/filereport".to_string(),
+            ..EnaClient::default()
+        };
+        let runs = client.resolve_one("SRR0000001").await.unwrap();
+        assert_eq!(runs[0].accession, "SRR0000001");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_resolve_encodes_query() {
+        let mut server = mockito::Server::new_async().await;
+        let field = "tax_id&result=analysis#";
+        let mock = server
+            .mock("GET", "/filereport")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("accession".into(), "SRR0000001".into()),
+                mockito::Matcher::UrlEncoded("result".into(), "read_run".into()),
+                mockito::Matcher::UrlEncoded(
+                    "fields".into(),
+                    format!("{},{}", FILE_FIELDS.join(","), field),
+                ),
+            ]))
+            .with_body(
+                r#"[{"run_accession":"SRR0000001","fastq_ftp":"","fastq_bytes":"","fastq_md5":"",
+                "submitted_ftp":"","submitted_bytes":"","submitted_md5":"",
+                "sra_ftp":"","sra_bytes":"","sra_md5":""}]"#,
+            )
+            .create_async()
+            .await;
+        let client = EnaClient {
+            base_url: server.url(),
+            fields: vec![field.to_string()],
+            ..EnaClient::default()
+        };
+        let runs = client.resolve_one("SRR0000001").await.unwrap();
+        assert_eq!(runs[0].accession, "SRR0000001");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_resolve_invalid_accession() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/filereport")
+            .match_query(mockito::Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
+        let client = EnaClient {
+            base_url: server.url(),
+            ..EnaClient::default()
+        };
+        let result = client.resolve_one("SRR1&result=analysis").await;
+        assert!(matches!(result, Err(Error::InvalidAccession(_))));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_builder_resolve_stream() {
+        let mut server = mockito::Server::new_async().await;
+        let found = server
+            .mock("GET", "/filereport")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "accession".into(),
+                "SRR0000001".into(),
+            ))
+            .match_header("user-agent", "my-service/1.0")
+            .with_body(
+                r#"[{"run_accession":"SRR0000001","fastq_ftp":"","fastq_bytes":"","fastq_md5":"",
+                "submitted_ftp":"","submitted_bytes":"","submitted_md5":"",
+                "sra_ftp":"","sra_bytes":"","sra_md5":""}]"#,
+            )
+            .create_async()
+            .await;
+        let not_found = server
+            .mock("GET", "/filereport")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "accession".into(),
+                "SRR0000002".into(),
+            ))
+            .with_body("")
+            .create_async()
+            .await;
+        let client = EnaClient::builder()
+            .base_url(server.url())
+            .num_requests(2)
+            .user_agent("my-service/1.0")
+            .timeout(Some(Duration::from_secs(10)))
+            .build()
+            .unwrap();
+        let mut outcomes = client
+            .resolve_stream(["SRR0000001".to_string(), "SRR0000002".to_string()])
+            .collect::<Vec<_>>()
+            .await;
+        outcomes.sort_by(|a, b| a.accession.cmp(&b.accession));
+        assert!(matches!(&outcomes[0].outcome, QueryOutcome::Found(runs) if runs.len() == 1));
+        assert!(matches!(outcomes[1].outcome, QueryOutcome::NotFound));
+        found.assert_async().await;
+        not_found.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_resolve_in_input_order() {
+        // The first accession is answered last, so the queries finish in
+        // the opposite order to the accessions
+        let mut server = mockito::Server::new_async().await;
+        for (accession, delay) in [("SRR0000001", 500), ("SRR0000002", 0)] {
+            let body = format!(
+                r#"[{{"run_accession":"{}","fastq_ftp":"","fastq_bytes":"","fastq_md5":"",
+                "submitted_ftp":"","submitted_bytes":"","submitted_md5":"",
+                "sra_ftp":"","sra_bytes":"","sra_md5":""}}]"#,
+                accession
+            );
+            server
+                .mock("GET", "/filereport")
+                .match_query(mockito::Matcher::UrlEncoded(
+                    "accession".into(),
+                    accession.into(),
+                ))
+                .with_chunked_body(move |w| {
+                    std::thread::sleep(Duration::from_millis(delay));
+                    w.write_all(body.as_bytes())
+                })
+                .create_async()
+                .await;
+        }
+        let client = EnaClient::builder()
+            .base_url(server.url())
+            .num_requests(2)
+            .build()
+            .unwrap();
+        let accessions = || vec!["SRR0000001".to_string(), "SRR0000002".to_string()];
+        let finished = client
+            .resolve_stream(accessions())
+            .map(|outcome| outcome.accession)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(finished, ["SRR0000002", "SRR0000001"]);
+        let in_order = client
+            .resolve_stream_in_order(accessions())
+            .map(|outcome| outcome.accession)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(in_order, accessions());
+        let runs = client
+            .resolve(accessions())
+            .await
+            .into_runs()
+            .into_iter()
+            .map(|run| run.accession)
+            .collect::<Vec<_>>();
+        assert_eq!(runs, accessions());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_runs() {
+        let mut server = mockito::Server::new_async().await;
+        let mut mocks = Vec::new();
+        for (accession, body) in [
+            ("SRR0000001", ""),
+            (
+                "SRR0000002",
+                r#"[{"run_accession":"SRR0000002","fastq_ftp":"","fastq_bytes":"","fastq_md5":"",
+                "submitted_ftp":"","submitted_bytes":"","submitted_md5":"",
+                "sra_ftp":"","sra_bytes":"","sra_md5":""}]"#,
+            ),
+        ] {
+            let mock = server
+                .mock("GET", "/filereport")
+                .match_query(mockito::Matcher::UrlEncoded(
+                    "accession".into(),
+                    accession.into(),
+                ))
+                .with_body(body)
+                .create_async()
+                .await;
+            mocks.push(mock);
+        }
+        let client = EnaClient::builder()
+            .base_url(server.url())
+            .num_requests(2)
+            .build()
+            .unwrap();
+        let results = client
+            .resolve_runs(["SRR0000001".to_string(), "SRR0000002".to_string()])
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(results.len(), 2);
+        assert!(
+            matches!(&results[0], Err(Error::NotFound(accession)) if accession == "SRR0000001")
+        );
+        assert_eq!(results[1].as_ref().unwrap().accession, "SRR0000002");
+    }
+
+    #[tokio::test]
+    async fn test_cache_per_base_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut servers = Vec::new();
+        let mut mocks = Vec::new();
+        for run in ["SRR0000001", "SRR0000002"] {
+            let mut server = mockito::Server::new_async().await;
+            let mock = server
+                .mock("GET", "/filereport")
+                .match_query(mockito::Matcher::Any)
+                .with_body(format!(
+                    r#"[{{"run_accession":"{}","fastq_ftp":"","fastq_bytes":"","fastq_md5":"",
+                    "submitted_ftp":"","submitted_bytes":"","submitted_md5":"",
+                    "sra_ftp":"","sra_bytes":"","sra_md5":""}}]"#,
+                    run
+                ))
+                .expect(1)
+                .create_async()
+                .await;
+            servers.push(server);
+            mocks.push(mock);
+        }
+        // The same accession from each server, and again from the first,
+        // with the same cache directory
+        let mut resolved = Vec::new();
+        for server in [&servers[0], &servers[1], &servers[0]] {
+            let client = EnaClient::builder()
+                .base_url(server.url())
+                .cache(Some(Cache::new(
+                    dir.path().to_path_buf(),
+                    Duration::from_secs(60),
+                )))
+                .build()
+                .unwrap();
+            let runs = client.resolve_one("PRJNA000001").await.unwrap();
+            resolved.push(runs[0].accession.clone());
+        }
+        assert_eq!(resolved, ["SRR0000001", "SRR0000002", "SRR0000001"]);
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_carries_on_after_failures() {
+        // Every accession is reported, whatever happened to the ones before it
+        let mut server = mockito::Server::new_async().await;
+        let failed = server
+            .mock("GET", "/filereport")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "accession".into(),
+                "SRR0000001".into(),
+            ))
+            .with_status(500)
+            .create_async()
+            .await;
+        let found = server
+            .mock("GET", "/filereport")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "accession".into(),
+                "SRR0000002".into(),
+            ))
+            .with_body(
+                r#"[{"run_accession":"SRR0000002","fastq_ftp":"","fastq_bytes":"","fastq_md5":"",
+                "submitted_ftp":"","submitted_bytes":"","submitted_md5":"",
+                "sra_ftp":"","sra_bytes":"","sra_md5":""}]"#,
+            )
+            .create_async()
+            .await;
+        let not_found = server
+            .mock("GET", "/filereport")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "accession".into(),
+                "SRR0000003".into(),
+            ))
+            .with_body("")
+            .create_async()
+            .await;
+        let client = retrying_client(&server, 0);
+        let report = client
+            .resolve(vec![
+                "SRR0000001".to_string(),
+                "SRR0000003".to_string(),
+                "SRR0000002".to_string(),
+            ])
+            .await;
+        assert_eq!(report.outcomes.len(), 3);
+        assert!(matches!(
+            report.outcomes[0].outcome,
+            QueryOutcome::Failed(Error::Http(_))
+        ));
+        assert!(matches!(report.outcomes[1].outcome, QueryOutcome::NotFound));
+        assert!(
+            matches!(&report.outcomes[2].outcome, QueryOutcome::Found(runs) if runs.len() == 1)
+        );
+        failed.assert_async().await;
+        not_found.assert_async().await;
+        found.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_resolve_no_concurrency() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/filereport")
+            .match_query(mockito::Matcher::Any)
+            .with_body("")
+            .create_async()
+            .await;
+        let client = EnaClient {
+            base_url: server.url(),
+            num_requests: 0,
+            ..EnaClient::default()
+        };
+        let report = tokio::time::timeout(
+            Duration::from_secs(10),
+            client.resolve(vec!["SRR0000001".to_string()]),
+        )
+        .await
+        .expect("a client with no concurrency still runs its queries");
+        assert!(matches!(report.outcomes[0].outcome, QueryOutcome::NotFound));
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_builder_defaults() {
+        let client = EnaClient::builder().num_requests(20).build().unwrap();
+        assert_eq!(client.base_url, ENA_URL);
+        assert_eq!(client.num_requests, 10);
+        let proxy = EnaClient::builder()
+            .proxy(Some("http://proxy.example.org:3128".to_string()))
+            .build();
+        assert!(proxy.is_ok());
+        let proxy = EnaClient::builder()
+            .proxy(Some("not a url".to_string()))
+            .build();
+        assert!(matches!(proxy, Err(Error::Http(_))));
+    }
+
+    /// A client for the mock server that retries without waiting long
+    fn retrying_client(server: &mockito::Server, max_retries: u32) -> EnaClient {
+        EnaClient::builder()
+            .base_url(server.url())
+            .retry(RetryPolicy {
+                max_retries,
+                base_delay: Duration::from_millis(1),
+                ..RetryPolicy::default()
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_text_retries() {
+        let mut server = mockito::Server::new_async().await;
+        let unavailable = server
+            .mock("GET", "/filereport")
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+        let rate_limited = server
+            .mock("GET", "/filereport")
+            .with_status(429)
+            .expect(1)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("GET", "/filereport")
+            .with_body("[]")
+            .expect(1)
+            .create_async()
+            .await;
+        let client = retrying_client(&server, 3);
+        let url = format!("{}/filereport", server.url());
+        assert_eq!(client.get_text("SRR0000001", &url).await.unwrap(), "[]");
+        unavailable.assert_async().await;
+        rate_limited.assert_async().await;
+        ok.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_get_text_retry_after() {
+        let mut server = mockito::Server::new_async().await;
+        let rate_limited = server
+            .mock("GET", "/filereport")
+            .with_status(429)
+            .with_header("retry-after", "1")
+            .expect(1)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("GET", "/filereport")
+            .with_body("[]")
+            .create_async()
+            .await;
+        // Without the Retry-After header, the retry would wait for an hour
+        let client = EnaClient::builder()
+            .base_url(server.url())
+            .retry(RetryPolicy {
+                max_retries: 1,
+                base_delay: Duration::from_secs(3600),
+                max_delay: Duration::from_secs(3600),
+            })
+            .build()
+            .unwrap();
+        let url = format!("{}/filereport", server.url());
+        let start = std::time::Instant::now();
+        let body =
+            tokio::time::timeout(Duration::from_secs(10), client.get_text("SRR0000001", &url))
+                .await
+                .expect("the Retry-After header was not honoured");
+        assert_eq!(body.unwrap(), "[]");
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        rate_limited.assert_async().await;
+        ok.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_get_text_runs_out_of_retries() {
+        let mut server = mockito::Server::new_async().await;
+        let unavailable = server
+            .mock("GET", "/filereport")
+            .with_status(503)
+            .expect(3)
+            .create_async()
+            .await;
+        let client = retrying_client(&server, 2);
+        let url = format!("{}/filereport", server.url());
+        match client.get_text("SRR0000001", &url).await {
+            Err(Error::Http(e)) => {
+                assert_eq!(e.status(), Some(reqwest::StatusCode::SERVICE_UNAVAILABLE))
+            }
+            result => panic!("expected the request to fail, got {:?}", result),
+        }
+        unavailable.assert_async().await;
+    }
+
+    #[test]
+    fn test_parse_ena_url() {
+        assert!(parse_ena_url("http://127.0.0.1:8080/ena/").is_ok());
+        assert!(parse_ena_url("ftp://ftp.sra.ebi.ac.uk").is_err());
+        assert!(parse_ena_url("www.ebi.ac.uk").is_err());
+    }
+}
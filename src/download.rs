@@ -0,0 +1,484 @@
+use crate::client::USER_AGENT;
+use crate::ftp::{timeout, FtpConnection, CONNECT_TIMEOUT, READ_TIMEOUT};
+use crate::verify::file_md5;
+use crate::{Error, Reads, Run};
+use futures::StreamExt;
+use reqwest::header::RANGE;
+use reqwest::{StatusCode, Url};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+/// The outcome of downloading a single read file
+#[derive(Debug)]
+pub struct DownloadOutcome {
+    /// The accession of the run the file belongs to
+    pub accession: String,
+    /// Where the file was downloaded from
+    pub url: String,
+    /// Where the file was downloaded to
+    pub path: PathBuf,
+    /// The number of bytes downloaded, or the reason the download failed
+    pub result: Result<u64, Error>,
+}
+
+/// Download the read files of the runs into the output directory, which is
+/// created if it does not exist. At most `num_requests` files are downloaded
+/// at the same time. Files are downloaded over HTTP(S) or FTP depending on
+/// their URL, and are written to a directory named after the run, keeping
+/// the name ENA gives them (e.g., SRR16298173/SRR16298173_1.fastq.gz), so
+/// that submitted files with the same name in different runs are kept apart.
+/// Runs that are listed more than once, e.g. because they were reached from
+/// two of the requested accessions, are downloaded once.
+///
+/// Each file is first downloaded to a `.part` file next to it. If a `.part`
+/// file is already there from an earlier attempt, the download carries on
+/// from where it stopped. Once the transfer is done, the size and MD5 of the
+/// file are checked against what ENA reports, and only then is it renamed
+/// into place. Files that are already in place and match are not downloaded
+/// again.
+///
+/// As with FTP, a download over HTTP(S) fails if connecting takes longer
+/// than 30 seconds, or if the server sends nothing for a minute, rather
+/// than hanging on a stalled transfer. There is no limit on how long a
+/// download that keeps going may take.
+pub async fn download_runs(
+    runs: &[Run],
+    output_dir: &Path,
+    num_requests: usize,
+) -> Result<Vec<DownloadOutcome>, Error> {
+    tokio::fs::create_dir_all(output_dir).await?;
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()?;
+    let mut seen = HashSet::new();
+    let outcomes = futures::stream::iter(
+        runs.iter()
+            .filter(|run| seen.insert(run.accession.as_str()))
+            .flat_map(|run| run.reads.iter().map(|read| (run.accession.as_str(), read))),
+    )
+    .map(|(accession, read)| {
+        let client = client.clone();
+        let path = output_dir.join(read.local_path(accession));
+        async move {
+            let result = download_file(&client, read, &path).await;
+            DownloadOutcome {
+                accession: accession.to_owned(),
+                url: read.url.clone(),
+                path,
+                result,
+            }
+        }
+    })
+    .buffer_unordered(num_requests.max(1))
+    .collect::<Vec<_>>()
+    .await;
+    Ok(outcomes)
+}
+
+impl Reads {
+    /// The name of the file, taken from the last part of its URL
+    pub(crate) fn file_name(&self) -> &str {
+        self.url.rsplit('/').next().unwrap_or(&self.url)
+    }
+
+    /// Where the file of the run is downloaded to, relative to the output
+    /// directory
+    pub(crate) fn local_path(&self, accession: &str) -> PathBuf {
+        Path::new(accession).join(self.file_name())
+    }
+}
+
+/// Download a single file, returning its size in bytes
+async fn download_file(client: &reqwest::Client, read: &Reads, path: &Path) -> Result<u64, Error> {
+    let url = Url::parse(&read.url).map_err(|e| invalid_url(&read.url, e))?;
+    if !matches!(url.scheme(), "ftp" | "http" | "https") {
+        return Err(invalid_url(
+            &read.url,
+            format!("unsupported scheme {}", url.scheme()),
+        ));
+    }
+    if tokio::fs::metadata(path).await.is_ok() && check_file(read, path).await.is_ok() {
+        log::info!("{} is already downloaded", path.display());
+        return Ok(read.bytes);
+    }
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let part = part_path(path);
+    let offset = match tokio::fs::metadata(&part).await {
+        Ok(metadata) if metadata.len() <= read.bytes => metadata.len(),
+        // The partial file is bigger than the whole file should be, so it
+        // cannot be resumed from
+        Ok(_) => 0,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.into()),
+    };
+    if offset > 0 {
+        log::info!("Resuming download of {} from byte {}", read.url, offset);
+    } else {
+        log::info!("Downloading {} to {}", read.url, path.display());
+    }
+    match url.scheme() {
+        "ftp" => download_ftp(&url, &part, offset).await?,
+        _ => download_http(client, url.as_str(), &part, offset).await?,
+    }
+    if let Err(e) = check_file(read, &part).await {
+        // A partial file that is too short can be resumed, anything else is
+        // corrupt and has to be downloaded again from scratch
+        if !matches!(e, Error::SizeMismatch { expected, actual, .. } if actual < expected) {
+            tokio::fs::remove_file(&part).await?;
+        }
+        return Err(e);
+    }
+    tokio::fs::rename(&part, path).await?;
+    Ok(read.bytes)
+}
+
+/// The path the file is downloaded to before it is checked
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Check a file has the size and MD5 reported by ENA. Files that ENA does not
+/// report an MD5 for are only checked for their size
+async fn check_file(read: &Reads, path: &Path) -> Result<(), Error> {
+    let actual = tokio::fs::metadata(path).await?.len();
+    if actual != read.bytes {
+        return Err(Error::SizeMismatch {
+            path: path.to_owned(),
+            expected: read.bytes,
+            actual,
+        });
+    }
+    if read.md5.is_empty() {
+        return Ok(());
+    }
+    let owned = path.to_owned();
+    let md5 = tokio::task::spawn_blocking(move || file_md5(&owned))
+        .await
+        .map_err(io::Error::other)??;
+    if !md5.eq_ignore_ascii_case(&read.md5) {
+        return Err(Error::ChecksumMismatch {
+            path: path.to_owned(),
+            expected: read.md5.clone(),
+            actual: md5,
+        });
+    }
+    Ok(())
+}
+
+/// Open the partial file, either to add to what is already there or to
+/// write it again from the start
+async fn open_part(part: &Path, append: bool) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(part)
+        .await
+}
+
+async fn download_http(
+    client: &reqwest::Client,
+    url: &str,
+    part: &Path,
+    offset: u64,
+) -> Result<(), Error> {
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+    let response = read_timeout(request.send()).await?;
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // There is nothing left to download, the size check will tell us
+        // whether the partial file is complete
+        return Ok(());
+    }
+    let mut response = response.error_for_status()?;
+    // Servers that do not support ranges send the whole file
+    let append = offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+    let mut file = open_part(part, append).await?;
+    while let Some(chunk) = read_timeout(response.chunk()).await? {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+/// Wait for the server to answer or send more of the file, failing if it
+/// sends nothing for too long
+async fn read_timeout<T>(
+    operation: impl std::future::Future<Output = reqwest::Result<T>>,
+) -> Result<T, Error> {
+    let operation = async { Ok(operation.await) };
+    Ok(timeout(READ_TIMEOUT, operation).await??)
+}
+
+async fn download_ftp(url: &Url, part: &Path, offset: u64) -> Result<(), Error> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| invalid_url(url.as_str(), "missing host"))?;
+    let mut connection = FtpConnection::connect(host, url.port().unwrap_or(21)).await?;
+    let (mut data, offset) = connection.retrieve(url.path(), offset).await?;
+    let mut file = open_part(part, offset > 0).await?;
+    data.copy_to(&mut file).await?;
+    file.flush().await?;
+    connection.finish().await?;
+    Ok(())
+}
+
+fn invalid_url(url: &str, reason: impl std::fmt::Display) -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Cannot download {}: {}", url, reason),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ftp::tests::serve_file;
+    use crate::FileType;
+    use md5::{Digest, Md5};
+
+    const CONTENTS: &[u8] = b"@read\nACGT\n+\nIIII\n";
+
+    fn run(url: String, md5: String) -> Run {
+        Run {
+            accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: vec![Reads {
+                url,
+                md5,
+                bytes: CONTENTS.len() as u64,
+                file_type: FileType::Fastq,
+            }],
+        }
+    }
+
+    fn md5() -> String {
+        format!("{:x}", Md5::digest(CONTENTS))
+    }
+
+    #[test]
+    fn test_file_name() {
+        let run = run(
+            "ftp://ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR1234567_1.fastq.gz".into(),
+            md5(),
+        );
+        assert_eq!(run.reads[0].file_name(), "SRR1234567_1.fastq.gz");
+        assert_eq!(
+            run.reads[0].local_path(&run.accession),
+            PathBuf::from("SRR1234567/SRR1234567_1.fastq.gz")
+        );
+        assert_eq!(
+            part_path(Path::new("out/SRR1234567_1.fastq.gz")),
+            PathBuf::from("out/SRR1234567_1.fastq.gz.part")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_download_ftp() {
+        let port = serve_file(CONTENTS).await;
+        let runs = vec![run(
+            format!("ftp://127.0.0.1:{}/vol1/SRR1234567_1.fastq", port),
+            md5(),
+        )];
+        let dir = tempfile::tempdir().unwrap();
+        let outcomes = download_runs(&runs, dir.path(), 1).await.unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), &18);
+        let contents = std::fs::read(dir.path().join("SRR1234567/SRR1234567_1.fastq")).unwrap();
+        assert_eq!(contents, CONTENTS);
+        assert!(!dir
+            .path()
+            .join("SRR1234567/SRR1234567_1.fastq.part")
+            .exists());
+    }
+
+    #[tokio::test]
+    async fn test_resume_ftp() {
+        let port = serve_file(CONTENTS).await;
+        let runs = vec![run(
+            format!("ftp://127.0.0.1:{}/vol1/SRR1234567_1.fastq", port),
+            md5(),
+        )];
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("SRR1234567")).unwrap();
+        std::fs::write(
+            dir.path().join("SRR1234567/SRR1234567_1.fastq.part"),
+            &CONTENTS[..6],
+        )
+        .unwrap();
+        let outcomes = download_runs(&runs, dir.path(), 1).await.unwrap();
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), &18);
+        let contents = std::fs::read(dir.path().join("SRR1234567/SRR1234567_1.fastq")).unwrap();
+        assert_eq!(contents, CONTENTS);
+    }
+
+    #[tokio::test]
+    async fn test_download_runs_apart() {
+        // Each server hands out its file once, so this fails if a run is
+        // downloaded twice
+        let mut runs = Vec::new();
+        for accession in ["SRR1234567", "SRR7654321"] {
+            let port = serve_file(CONTENTS).await;
+            let mut run = run(format!("ftp://127.0.0.1:{}/vol1/sample.bam", port), md5());
+            run.accession = accession.to_string();
+            run.reads[0].file_type = FileType::Submitted;
+            runs.push(run);
+        }
+        // The first run is listed again, as it is when two requested
+        // accessions expand into it
+        runs.push(runs[0].clone());
+        let dir = tempfile::tempdir().unwrap();
+        let outcomes = download_runs(&runs, dir.path(), 0).await.unwrap();
+        assert_eq!(outcomes.len(), 2);
+        for outcome in &outcomes {
+            assert_eq!(outcome.result.as_ref().unwrap(), &18);
+            let contents = std::fs::read(dir.path().join(&outcome.accession).join("sample.bam"));
+            assert_eq!(contents.unwrap(), CONTENTS);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_without_md5() {
+        let port = serve_file(CONTENTS).await;
+        let runs = vec![run(
+            format!("ftp://127.0.0.1:{}/vol1/SRR1234567_1.fastq", port),
+            String::new(),
+        )];
+        let dir = tempfile::tempdir().unwrap();
+        let outcomes = download_runs(&runs, dir.path(), 1).await.unwrap();
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), &18);
+        assert!(dir.path().join("SRR1234567/SRR1234567_1.fastq").exists());
+    }
+
+    /// Download a file over HTTP with the first `part` bytes of it already
+    /// in its `.part` file, from a server that answers the request for the
+    /// rest of it with the status and body
+    async fn resume_http(part: usize, status: usize, body: &[u8]) -> Vec<DownloadOutcome> {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/vol1/SRR1234567_1.fastq")
+            .match_header("range", format!("bytes={}-", part).as_str())
+            .with_status(status)
+            .with_body(body)
+            .create_async()
+            .await;
+        let runs = vec![run(
+            format!("{}/vol1/SRR1234567_1.fastq", server.url()),
+            md5(),
+        )];
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("SRR1234567")).unwrap();
+        std::fs::write(
+            dir.path().join("SRR1234567/SRR1234567_1.fastq.part"),
+            &CONTENTS[..part],
+        )
+        .unwrap();
+        let outcomes = download_runs(&runs, dir.path(), 1).await.unwrap();
+        mock.assert_async().await;
+        let contents = std::fs::read(dir.path().join("SRR1234567/SRR1234567_1.fastq")).unwrap();
+        assert_eq!(contents, CONTENTS);
+        outcomes
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_download_http_stalled() {
+        // A server that starts sending the file but never finishes
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = tokio::io::AsyncReadExt::read(&mut stream, &mut request).await;
+            let head = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n",
+                CONTENTS.len()
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            stream.write_all(&CONTENTS[..6]).await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        let runs = vec![run(
+            format!("http://127.0.0.1:{}/vol1/SRR1234567_1.fastq", port),
+            md5(),
+        )];
+        let dir = tempfile::tempdir().unwrap();
+        let outcomes = download_runs(&runs, dir.path(), 1).await.unwrap();
+        assert!(matches!(
+            &outcomes[0].result,
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::TimedOut
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_resume_http_partial_content() {
+        let outcomes = resume_http(6, 206, &CONTENTS[6..]).await;
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), &18);
+    }
+
+    #[tokio::test]
+    async fn test_resume_http_without_ranges() {
+        // The server sends the whole file, which replaces the partial one
+        let outcomes = resume_http(6, 200, CONTENTS).await;
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), &18);
+    }
+
+    #[tokio::test]
+    async fn test_resume_http_complete() {
+        // The partial file is already complete, so there is nothing left
+        let outcomes = resume_http(CONTENTS.len(), 416, b"").await;
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), &18);
+    }
+
+    #[tokio::test]
+    async fn test_download_checksum_mismatch() {
+        let port = serve_file(CONTENTS).await;
+        let url = format!("ftp://127.0.0.1:{}/vol1/SRR1234567_1.fastq", port);
+        let runs = vec![run(url, "0".repeat(32))];
+        let dir = tempfile::tempdir().unwrap();
+        let outcomes = download_runs(&runs, dir.path(), 1).await.unwrap();
+        assert!(matches!(
+            outcomes[0].result,
+            Err(Error::ChecksumMismatch { .. })
+        ));
+        assert!(!dir.path().join("SRR1234567/SRR1234567_1.fastq").exists());
+        assert!(!dir
+            .path()
+            .join("SRR1234567/SRR1234567_1.fastq.part")
+            .exists());
+    }
+
+    #[tokio::test]
+    async fn test_skip_downloaded() {
+        // Nothing is listening on this port, so this fails if a download is attempted
+        let runs = vec![run(
+            "ftp://127.0.0.1:1/vol1/SRR1234567_1.fastq".into(),
+            md5(),
+        )];
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("SRR1234567")).unwrap();
+        std::fs::write(dir.path().join("SRR1234567/SRR1234567_1.fastq"), CONTENTS).unwrap();
+        let outcomes = download_runs(&runs, dir.path(), 1).await.unwrap();
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), &18);
+    }
+
+    #[tokio::test]
+    async fn test_download_unsupported_scheme() {
+        let runs = vec![run("s3://bucket/SRR1234567_1.fastq".into(), md5())];
+        let dir = tempfile::tempdir().unwrap();
+        let outcomes = download_runs(&runs, dir.path(), 1).await.unwrap();
+        assert!(matches!(outcomes[0].result, Err(Error::Io(_))));
+        assert!(!dir.path().join("SRR1234567/SRR1234567_1.fastq").exists());
+    }
+}
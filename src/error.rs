@@ -0,0 +1,115 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// The errors that can be returned by phcue-ck. More kinds of error may be
+/// added, so matches on it need a wildcard arm
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The accession is not one that ENA can expand into runs
+    InvalidAccession(String),
+    /// Reading the accessions or writing the output failed
+    Io(std::io::Error),
+    /// The URL of the ENA API, made up of its base URL and endpoint, is not
+    /// a valid URL
+    InvalidUrl { url: String, message: String },
+    /// The request to the ENA API failed
+    Http(reqwest::Error),
+    /// The ENA API returned a response we could not make sense of
+    MalformedResponse { accession: String, message: String },
+    /// The ENA API did not return any runs for the accession
+    NotFound(String),
+    /// The accession is not in the cache or the filereport, and ENA cannot be
+    /// queried because we are offline
+    NotAvailableOffline(String),
+    /// A downloaded file does not have the size reported by ENA
+    SizeMismatch {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    /// A downloaded file does not have the MD5 reported by ENA
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidAccession(accession) => {
+                write!(f, "{} is not a valid accession number", accession)
+            }
+            Error::Io(e) => write!(f, "{}", e),
+            Error::InvalidUrl { url, message } => {
+                write!(f, "{} is not a valid URL for the ENA API: {}", url, message)
+            }
+            Error::Http(e) => write!(f, "{}", e),
+            Error::MalformedResponse { accession, message } => write!(
+                f,
+                "Could not parse the ENA response for accession {}: {}",
+                accession, message
+            ),
+            Error::NotFound(accession) => {
+                write!(f, "No runs found in ENA for accession {}", accession)
+            }
+            Error::NotAvailableOffline(accession) => write!(
+                f,
+                "Accession {} is not in the cache or the filereport, and ENA is not queried offline",
+                accession
+            ),
+            Error::SizeMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} has {} bytes, but ENA reports {} bytes",
+                path.display(),
+                actual,
+                expected
+            ),
+            Error::ChecksumMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} has MD5 {}, but ENA reports MD5 {}",
+                path.display(),
+                actual,
+                expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+        Error::Io(e.into())
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
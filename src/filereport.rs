@@ -0,0 +1,151 @@
+use crate::Error;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+/// The fields of a filereport row holding accessions that the row can be
+/// looked up by, from the run itself up to the study it belongs to
+const ACCESSION_FIELDS: [&str; 6] = [
+    "run_accession",
+    "experiment_accession",
+    "sample_accession",
+    "secondary_sample_accession",
+    "study_accession",
+    "secondary_study_accession",
+];
+
+/// A dump of the ENA filereport for a set of runs, saved as the TSV or JSON
+/// returned by the ENA API, so that accessions can be resolved without
+/// querying ENA. Each row needs the fields describing the files of the run
+/// (which ENA returns by default), and can only be found by a container
+/// accession if it has the field for it, e.g., study_accession
+#[derive(Debug, Clone, Default)]
+pub struct Filereport {
+    rows: Vec<BTreeMap<String, serde_json::Value>>,
+}
+
+impl Filereport {
+    /// Read a filereport saved as TSV or JSON, telling them apart by whether
+    /// it starts with a JSON array
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|message| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            ))
+        })
+    }
+
+    /// Parse a filereport from the text of the TSV or JSON
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        if text.trim_start().starts_with('[') {
+            let rows = serde_json::from_str(text).map_err(|e| e.to_string())?;
+            return Ok(Self { rows });
+        }
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .from_reader(text.as_bytes());
+        let header = rdr.headers().map_err(|e| e.to_string())?.clone();
+        if !header.iter().any(|name| name == "run_accession") {
+            return Err("there is no run_accession column".to_string());
+        }
+        let mut rows = Vec::new();
+        for record in rdr.records() {
+            let record = record.map_err(|e| e.to_string())?;
+            let row = header
+                .iter()
+                .zip(record.iter())
+                .map(|(name, value)| (name.to_string(), value.into()))
+                .collect();
+            rows.push(row);
+        }
+        Ok(Self { rows })
+    }
+
+    /// The number of runs in the filereport
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Whether there are no runs in the filereport
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// The fields that none of the runs in the filereport have, so runs
+    /// resolved from it have no value for them
+    pub fn missing_fields<'a>(&self, fields: &'a [String]) -> Vec<&'a str> {
+        fields
+            .iter()
+            .filter(|field| !self.rows.iter().any(|row| row.contains_key(*field)))
+            .map(|field| field.as_str())
+            .collect()
+    }
+
+    /// The rows for the runs belonging to the accession, as the JSON body
+    /// that the ENA API would have returned for it, if there are any
+    pub(crate) fn lookup(&self, accession: &str) -> Option<String> {
+        let rows = self
+            .rows
+            .iter()
+            .filter(|row| {
+                ACCESSION_FIELDS.iter().any(|field| {
+                    row.get(*field).and_then(|value| value.as_str()) == Some(accession)
+                })
+            })
+            .collect::<Vec<_>>();
+        if rows.is_empty() {
+            return None;
+        }
+        Some(serde_json::to_string(&rows).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TSV: &str = "run_accession\tstudy_accession\tfastq_ftp\n\
+                       SRR0000001\tPRJNA000001\tftp.sra.ebi.ac.uk/SRR0000001.fastq.gz\n\
+                       SRR0000002\tPRJNA000001\t\n";
+
+    #[test]
+    fn test_parse_tsv() {
+        let filereport = Filereport::parse(TSV).unwrap();
+        assert_eq!(filereport.len(), 2);
+        let body = filereport.lookup("SRR0000002").unwrap();
+        assert_eq!(
+            body,
+            r#"[{"fastq_ftp":"","run_accession":"SRR0000002","study_accession":"PRJNA000001"}]"#
+        );
+        let rows: Vec<serde_json::Value> =
+            serde_json::from_str(&filereport.lookup("PRJNA000001").unwrap()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(filereport.lookup("SRR0000003"), None);
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        let filereport = Filereport::parse(TSV).unwrap();
+        let fields = ["study_accession", "tax_id", "read_count"].map(String::from);
+        assert_eq!(
+            filereport.missing_fields(&fields),
+            vec!["tax_id", "read_count"]
+        );
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let filereport =
+            Filereport::parse(r#"[{"run_accession":"SRR0000001","fastq_ftp":""}]"#).unwrap();
+        assert_eq!(filereport.len(), 1);
+        assert!(filereport.lookup("SRR0000001").is_some());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Filereport::parse("accession\tfastq_ftp\nSRR0000001\t\n").is_err());
+        assert!(Filereport::parse("[{").is_err());
+    }
+}
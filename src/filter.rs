@@ -0,0 +1,224 @@
+use crate::Run;
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+/// A condition on a run, used to drop the runs that cannot be used before
+/// they are written out. Conditions on the metadata of a run need the ENA
+/// fields given by [`RunFilter::fields`], and a run without a value for the
+/// field does not match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunFilter {
+    /// The sequencing platform, e.g. ILLUMINA, ignoring case
+    Platform(String),
+    /// The library layout, SINGLE or PAIRED, ignoring case
+    Layout(String),
+    /// The library strategy, e.g. WGS, ignoring case
+    Strategy(String),
+    /// At least this many bases were sequenced
+    MinBases(u64),
+    /// The selected files of the run add up to at most this many bytes
+    MaxBytes(u64),
+    /// First made public on or after this date
+    PublishedAfter(Date),
+    /// Every one of the filters matches
+    All(Vec<RunFilter>),
+}
+
+impl RunFilter {
+    /// Whether the run is kept by this filter
+    pub fn matches(&self, run: &Run) -> bool {
+        let text = |field: &Option<String>, expected: &str| {
+            field
+                .as_deref()
+                .is_some_and(|value| value.eq_ignore_ascii_case(expected))
+        };
+        match self {
+            RunFilter::Platform(platform) => text(&run.metadata.instrument_platform, platform),
+            RunFilter::Layout(layout) => text(&run.metadata.library_layout, layout),
+            RunFilter::Strategy(strategy) => text(&run.metadata.library_strategy, strategy),
+            RunFilter::MinBases(min) => run.metadata.base_count.is_some_and(|n| n >= *min),
+            RunFilter::MaxBytes(max) => {
+                run.reads.iter().map(|read| read.bytes).sum::<u64>() <= *max
+            }
+            RunFilter::PublishedAfter(date) => run
+                .metadata
+                .first_public
+                .as_deref()
+                .and_then(|published| published.parse::<Date>().ok())
+                .is_some_and(|published| published >= *date),
+            RunFilter::All(filters) => filters.iter().all(|filter| filter.matches(run)),
+        }
+    }
+
+    /// The ENA fields needed to check this filter
+    pub fn fields(&self) -> Vec<&'static str> {
+        match self {
+            RunFilter::Platform(_) => vec!["instrument_platform"],
+            RunFilter::Layout(_) => vec!["library_layout"],
+            RunFilter::Strategy(_) => vec!["library_strategy"],
+            RunFilter::MinBases(_) => vec!["base_count"],
+            RunFilter::MaxBytes(_) => vec![],
+            RunFilter::PublishedAfter(_) => vec!["first_public"],
+            RunFilter::All(filters) => {
+                let mut fields = Vec::new();
+                for field in filters.iter().flat_map(RunFilter::fields) {
+                    if !fields.contains(&field) {
+                        fields.push(field);
+                    }
+                }
+                fields
+            }
+        }
+    }
+
+    /// A filter matching the runs that both this filter and the other match
+    pub fn and(self, other: RunFilter) -> RunFilter {
+        match self {
+            RunFilter::All(mut filters) => {
+                filters.push(other);
+                RunFilter::All(filters)
+            }
+            filter => RunFilter::All(vec![filter, other]),
+        }
+    }
+}
+
+/// Keep the runs that match the filter
+pub fn filter_runs(runs: Vec<Run>, filter: &RunFilter) -> Vec<Run> {
+    runs.into_iter().filter(|run| filter.matches(run)).collect()
+}
+
+/// A calendar date, in the YYYY-MM-DD format ENA uses for first_public.
+/// Dates order by year, then month, then day
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    year: u16,
+    month: u8,
+    day: u8,
+}
+
+impl Date {
+    /// The date, if the day exists in that month and year
+    pub fn new(year: u16, month: u8, day: u8) -> Option<Date> {
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let days = match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => return None,
+        };
+        (1..=days)
+            .contains(&day)
+            .then_some(Date { year, month, day })
+    }
+}
+
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(date: &str) -> Result<Self, Self::Err> {
+        static DATE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"^(\d{4})-(\d{2})-(\d{2})$").unwrap());
+        DATE.captures(date)
+            .and_then(|captures| {
+                Date::new(
+                    captures[1].parse().ok()?,
+                    captures[2].parse().ok()?,
+                    captures[3].parse().ok()?,
+                )
+            })
+            .ok_or_else(|| format!("{:?} is not a date in the format YYYY-MM-DD", date))
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Parse a date given as YYYY-MM-DD, the format ENA uses for first_public
+pub(crate) fn parse_date(date: &str) -> Result<Date, String> {
+    date.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::fastq_run;
+
+    fn run() -> Run {
+        let mut run = fastq_run(&["SRR1234567_1.fastq.gz", "SRR1234567_2.fastq.gz"]);
+        for (name, value) in [
+            ("instrument_platform", "ILLUMINA"),
+            ("library_layout", "PAIRED"),
+            ("library_strategy", "WGS"),
+            ("base_count", "1000"),
+            ("first_public", "2021-06-01"),
+        ] {
+            run.metadata.set(name, value).unwrap();
+        }
+        run
+    }
+
+    #[test]
+    fn test_matches() {
+        let run = run();
+        assert!(RunFilter::Platform("illumina".to_string()).matches(&run));
+        assert!(!RunFilter::Layout("SINGLE".to_string()).matches(&run));
+        assert!(RunFilter::Strategy("WGS".to_string()).matches(&run));
+        assert!(RunFilter::MinBases(1000).matches(&run));
+        assert!(!RunFilter::MinBases(1001).matches(&run));
+        assert!(RunFilter::MaxBytes(246).matches(&run));
+        assert!(!RunFilter::MaxBytes(245).matches(&run));
+        assert!(RunFilter::PublishedAfter(Date::new(2021, 6, 1).unwrap()).matches(&run));
+        assert!(RunFilter::PublishedAfter(Date::new(2020, 12, 31).unwrap()).matches(&run));
+        assert!(!RunFilter::PublishedAfter(Date::new(2021, 6, 2).unwrap()).matches(&run));
+    }
+
+    #[test]
+    fn test_missing_metadata() {
+        let run = fastq_run(&["SRR1234567.fastq.gz"]);
+        assert!(!RunFilter::Platform("ILLUMINA".to_string()).matches(&run));
+        assert!(!RunFilter::MinBases(0).matches(&run));
+        assert!(RunFilter::MaxBytes(123).matches(&run));
+        let mut run = run;
+        run.metadata.set("first_public", "not a date").unwrap();
+        assert!(!RunFilter::PublishedAfter(Date::new(2000, 1, 1).unwrap()).matches(&run));
+    }
+
+    #[test]
+    fn test_and() {
+        let filter = RunFilter::Platform("ILLUMINA".to_string())
+            .and(RunFilter::MaxBytes(1000))
+            .and(RunFilter::MinBases(10));
+        assert_eq!(filter.fields(), vec!["instrument_platform", "base_count"]);
+        assert!(filter.matches(&run()));
+        let filter = filter.and(RunFilter::Layout("SINGLE".to_string()));
+        assert!(!filter.matches(&run()));
+        let mut other = run();
+        other.accession = "SRR0000001".to_string();
+        other.metadata.library_layout = Some("SINGLE".to_string());
+        let runs = filter_runs(vec![run(), other], &filter);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].accession, "SRR0000001");
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(
+            parse_date("2021-01-01").unwrap(),
+            Date::new(2021, 1, 1).unwrap()
+        );
+        assert_eq!(parse_date("2020-02-29").unwrap().to_string(), "2020-02-29");
+        assert!(parse_date("2021-1-1").is_err());
+        assert!(parse_date("2021-13-01").is_err());
+        assert!(parse_date("2021-02-29").is_err());
+        assert!(parse_date("2021-04-31").is_err());
+        assert!(parse_date("2021-01-00").is_err());
+        assert!(parse_date("01/01/2021").is_err());
+    }
+}
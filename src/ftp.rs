@@ -0,0 +1,319 @@
+//! A minimal FTP client, with just enough of the protocol to retrieve files
+//! anonymously from the ENA FTP server in passive mode
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// How long to wait for a connection to the server to be made
+pub(crate) const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to wait for the server to send anything, on either connection,
+/// before giving up on it
+pub(crate) const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A connection to an FTP server, logged in anonymously
+pub(crate) struct FtpConnection {
+    control: BufReader<TcpStream>,
+}
+
+/// The connection the contents of a file being retrieved are sent over
+pub(crate) struct DataConnection {
+    stream: TcpStream,
+}
+
+impl FtpConnection {
+    /// Connect to the server and log in as the anonymous user
+    pub(crate) async fn connect(host: &str, port: u16) -> io::Result<Self> {
+        let stream = connect(host, port).await?;
+        let mut connection = Self {
+            control: BufReader::new(stream),
+        };
+        connection.expect_reply(&[220]).await?;
+        let (code, _) = connection.command("USER anonymous", &[230, 331]).await?;
+        if code == 331 {
+            connection.command("PASS anonymous@", &[230]).await?;
+        }
+        connection.command("TYPE I", &[200]).await?;
+        Ok(connection)
+    }
+
+    /// Start retrieving a file from the given offset, returning the data
+    /// connection the contents of the file can be read from, and the offset
+    /// the server will actually start from, which is 0 if it does not support
+    /// restarting transfers. Once all the data has been read, `finish` must be
+    /// called to check the transfer completed
+    pub(crate) async fn retrieve(
+        &mut self,
+        path: &str,
+        offset: u64,
+    ) -> io::Result<(DataConnection, u64)> {
+        let (_, message) = self.command("PASV", &[227]).await?;
+        let (host, port) = parse_pasv(&message)?;
+        let data = DataConnection {
+            stream: connect(&host, port).await?,
+        };
+        let offset = match offset {
+            0 => 0,
+            _ => match self.send(&format!("REST {}", offset)).await? {
+                (350, _) => offset,
+                _ => 0,
+            },
+        };
+        self.command(&format!("RETR {}", path), &[125, 150]).await?;
+        Ok((data, offset))
+    }
+
+    /// Wait for the server to confirm the transfer is complete, and log out
+    pub(crate) async fn finish(mut self) -> io::Result<()> {
+        self.expect_reply(&[226, 250]).await?;
+        // The file has been transferred, so it does not matter if this fails
+        let _ = self.command("QUIT", &[221]).await;
+        Ok(())
+    }
+
+    /// Send a command, and check the reply has one of the expected codes
+    async fn command(&mut self, command: &str, expected: &[u16]) -> io::Result<(u16, String)> {
+        self.write_command(command).await?;
+        self.expect_reply(expected).await
+    }
+
+    /// Send a command, and return the reply whatever its code
+    async fn send(&mut self, command: &str) -> io::Result<(u16, String)> {
+        self.write_command(command).await?;
+        self.read_reply().await
+    }
+
+    async fn write_command(&mut self, command: &str) -> io::Result<()> {
+        let control = self.control.get_mut();
+        control.write_all(command.as_bytes()).await?;
+        control.write_all(b"\r\n").await
+    }
+
+    /// Read a reply, and check it has one of the expected codes
+    async fn expect_reply(&mut self, expected: &[u16]) -> io::Result<(u16, String)> {
+        let (code, message) = self.read_reply().await?;
+        if expected.contains(&code) {
+            Ok((code, message))
+        } else {
+            Err(io::Error::other(format!(
+                "Unexpected reply from FTP server: {} {}",
+                code, message
+            )))
+        }
+    }
+
+    /// Read a reply from the server. Replies spanning multiple lines start
+    /// with "123-" and end with a line starting with "123 "
+    async fn read_reply(&mut self) -> io::Result<(u16, String)> {
+        let mut message = String::new();
+        let mut code: Option<u16> = None;
+        loop {
+            let mut line = String::new();
+            if timeout(READ_TIMEOUT, self.control.read_line(&mut line)).await? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "FTP server closed the connection",
+                ));
+            }
+            let line = line.trim_end();
+            let line_code = line.get(..3).and_then(|c| c.parse::<u16>().ok());
+            let code = match code {
+                Some(code) => code,
+                None => *code.insert(line_code.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid reply from FTP server: {}", line),
+                    )
+                })?),
+            };
+            message.push_str(line.get(4..).unwrap_or(""));
+            if line_code == Some(code) && line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, message));
+            }
+            message.push('\n');
+        }
+    }
+}
+
+impl DataConnection {
+    /// Copy the contents of the file to the writer, returning the number of
+    /// bytes copied
+    pub(crate) async fn copy_to<W: AsyncWrite + Unpin>(
+        &mut self,
+        writer: &mut W,
+    ) -> io::Result<u64> {
+        let mut buffer = vec![0; 1 << 16];
+        let mut copied = 0;
+        loop {
+            let n = timeout(READ_TIMEOUT, self.stream.read(&mut buffer)).await?;
+            if n == 0 {
+                return Ok(copied);
+            }
+            writer.write_all(&buffer[..n]).await?;
+            copied += n as u64;
+        }
+    }
+}
+
+/// Connect to the server, giving up if it takes too long
+async fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    timeout(CONNECT_TIMEOUT, TcpStream::connect((host, port))).await
+}
+
+/// Wait for an operation on a connection, failing if it takes longer than
+/// the duration, as when the server stops responding
+pub(crate) async fn timeout<T>(
+    duration: Duration,
+    operation: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    tokio::time::timeout(duration, operation)
+        .await
+        .unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "FTP server did not respond in time",
+            ))
+        })
+}
+
+/// Parse the address of the data connection out of the reply to PASV, which
+/// looks like "Entering Passive Mode (193,62,197,74,195,80)."
+fn parse_pasv(message: &str) -> io::Result<(String, u16)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid reply to PASV: {}", message),
+        )
+    };
+    let start = message.find('(').ok_or_else(invalid)?;
+    let end = message[start..].find(')').ok_or_else(invalid)? + start;
+    let numbers = message[start + 1..end]
+        .split(',')
+        .map(|n| n.trim().parse::<u8>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid())?;
+    if numbers.len() != 6 {
+        return Err(invalid());
+    }
+    let host = format!(
+        "{}.{}.{}.{}",
+        numbers[0], numbers[1], numbers[2], numbers[3]
+    );
+    let port = (numbers[4] as u16) << 8 | numbers[5] as u16;
+    Ok((host, port))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Start a fake FTP server on localhost that serves a single file, and
+    /// return the port it is listening on. Transfers can be restarted from
+    /// an offset with REST
+    pub(crate) async fn serve_file(contents: &'static [u8]) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            let mut data_listener: Option<TcpListener> = None;
+            let mut offset = 0;
+            writer
+                .write_all(b"220-Welcome\r\n220 Ready\r\n")
+                .await
+                .unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).await.unwrap() > 0 {
+                let mut words = line.trim_end().split(' ');
+                let reply = match words.next().unwrap() {
+                    "USER" => "331 Password please\r\n".to_string(),
+                    "PASS" => "230 Logged in\r\n".to_string(),
+                    "TYPE" => "200 Binary\r\n".to_string(),
+                    "PASV" => {
+                        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                        let port = listener.local_addr().unwrap().port();
+                        data_listener = Some(listener);
+                        format!(
+                            "227 Entering Passive Mode (127,0,0,1,{},{}).\r\n",
+                            port >> 8,
+                            port & 0xff
+                        )
+                    }
+                    "REST" => {
+                        offset = words.next().unwrap().parse::<usize>().unwrap();
+                        "350 Restarting\r\n".to_string()
+                    }
+                    "RETR" => {
+                        writer.write_all(b"150 Sending\r\n").await.unwrap();
+                        let listener = data_listener.take().unwrap();
+                        let (mut data, _) = listener.accept().await.unwrap();
+                        data.write_all(&contents[offset..]).await.unwrap();
+                        offset = 0;
+                        drop(data);
+                        "226 Done\r\n".to_string()
+                    }
+                    "QUIT" => {
+                        writer.write_all(b"221 Bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => "502 Not implemented\r\n".to_string(),
+                };
+                writer.write_all(reply.as_bytes()).await.unwrap();
+                line.clear();
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_parse_pasv() {
+        let (host, port) = parse_pasv("Entering Passive Mode (193,62,197,74,195,80).").unwrap();
+        assert_eq!(host, "193.62.197.74");
+        assert_eq!(port, 195 * 256 + 80);
+        assert!(parse_pasv("Entering Passive Mode").is_err());
+        assert!(parse_pasv("Entering Passive Mode (1,2,3)").is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connect_timeout() {
+        // A server that accepts the connection but never replies
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        let error = FtpConnection::connect("127.0.0.1", port)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve() {
+        let port = serve_file(b"@read\nACGT\n+\nIIII\n").await;
+        let mut connection = FtpConnection::connect("127.0.0.1", port).await.unwrap();
+        let (mut data, offset) = connection.retrieve("/vol1/read.fastq", 0).await.unwrap();
+        let mut contents = Vec::new();
+        data.copy_to(&mut contents).await.unwrap();
+        connection.finish().await.unwrap();
+        assert_eq!(offset, 0);
+        assert_eq!(contents, b"@read\nACGT\n+\nIIII\n");
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_from_offset() {
+        let port = serve_file(b"@read\nACGT\n+\nIIII\n").await;
+        let mut connection = FtpConnection::connect("127.0.0.1", port).await.unwrap();
+        let (mut data, offset) = connection.retrieve("/vol1/read.fastq", 6).await.unwrap();
+        let mut contents = Vec::new();
+        data.copy_to(&mut contents).await.unwrap();
+        connection.finish().await.unwrap();
+        assert_eq!(offset, 6);
+        assert_eq!(contents, b"ACGT\n+\nIIII\n");
+    }
+}
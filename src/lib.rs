@@ -1,10 +1,51 @@
-use clap::{Parser, ValueEnum};
-use futures::StreamExt;
+use clap::ValueEnum;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
-use std::process::exit;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+mod cache;
+mod cli;
+mod client;
+mod download;
+mod error;
+mod filereport;
+mod filter;
+mod ftp;
+mod manifest;
+mod metadata;
+mod output;
+mod report;
+mod retry;
+mod samplesheet;
+mod verify;
+
+pub use cache::{Cache, CacheStats};
+pub use cli::{
+    parse_args, Args, CacheAction, CacheArgs, CacheOptions, Command, DownloadArgs, QueryArgs,
+    VerifyArgs,
+};
+pub use client::{EnaClient, EnaClientBuilder, ENA_URL};
+pub use download::{download_runs, DownloadOutcome};
+pub use error::Error;
+pub use filereport::Filereport;
+pub use filter::{filter_runs, Date, RunFilter};
+pub use manifest::{print_aria2, print_curl_config, print_md5sum, print_wget_script};
+pub use metadata::Metadata;
+pub use output::{
+    metadata_fields, print_csv, print_csv_long, print_csv_wide, read_csv, read_csv_long,
+    read_csv_wide, CsvRunWriter, JsonlRunWriter, RunWriter,
+};
+pub use report::{AccessionOutcome, QueryOutcome, QueryReport, ReportFormat};
+pub use retry::RetryPolicy;
+pub use samplesheet::{
+    print_samplesheet, print_samplesheet_fetchngs, read_sample_names, SampleFrom,
+    SamplesheetOptions, FETCHNGS_FIELDS,
+};
+pub use verify::{verify_runs, VerifyOutcome, VerifyStatus};
 
 /// A struct to hold the data returned from the ENA API
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -19,186 +60,395 @@ struct ENAApiResponse {
     sra_ftp: String,
     sra_bytes: String,
     sra_md5: String,
+    /// Any other fields that were requested
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
 }
 
 /// A struct to hold the parsed data from the ENA API and return it to the user
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(from = "ENAApiResponse")]
+/// It is built from the ENA API response, and (de)serialised in the format
+/// phcue-ck writes out, so that runs written as JSON can be read back in
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Run {
     pub accession: String,
+    /// The study, project, sample or experiment accession that was queried
+    /// to find this run, if the run was not queried directly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_accession: Option<String>,
     reads: Vec<Reads>,
+    /// The metadata fields requested with `--fields`
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: Metadata,
 }
 
 impl Run {
     /// Clean single end reads if there are paired end reads too
     /// This is if the user does not wish to have the single end reads, and
     /// keep only the paired end reads
+    /// Only FASTQ files are considered, and the files are told apart by the
+    /// read number at the end of their name rather than their position
     pub fn clean_single_end(&mut self) {
-        if self.reads.len() == 3 {
-            self.reads.remove(0);
+        let fastq = self.files(FileType::Fastq);
+        let has_pair = [ReadRole::R1, ReadRole::R2]
+            .iter()
+            .all(|role| fastq.iter().any(|read| read.role() == *role));
+        if has_pair {
+            self.reads.retain(|read| {
+                read.file_type != FileType::Fastq || read.role() != ReadRole::Single
+            });
+        }
+    }
+
+    /// Keep only the files of the types selected by the filter
+    pub fn select_file_types(&mut self, filter: &FileTypeFilter) {
+        self.reads.retain(|read| filter.includes(read.file_type));
+    }
+
+    /// The files of a single type, in the order ENA lists them
+    fn files(&self, file_type: FileType) -> Vec<&Reads> {
+        self.reads
+            .iter()
+            .filter(|read| read.file_type == file_type)
+            .collect()
+    }
+}
+
+/// What a FASTQ file holds, worked out from the read number at the end of
+/// its name, e.g., SRR16298173_1.fastq.gz holds the first reads of a pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReadRole {
+    /// The reads of a single end run, or the unpaired reads of a paired end
+    /// run, which have no read number
+    Single,
+    /// The first reads of a pair
+    R1,
+    /// The second reads of a pair
+    R2,
+    /// Reads numbered 3 or above, which hold index or barcode reads
+    Index(u8),
+    /// The name does not look like a FASTQ file with a read number
+    Unknown,
+}
+
+impl ReadRole {
+    /// Work out the role of a file from its name, which is either the name
+    /// ENA gives it, the run accession followed by the read number, or the
+    /// name Illumina's tools give it, with the read (R) or index (I) number
+    /// before the chunk number, e.g., sample_S1_L001_R1_001.fastq.gz
+    pub fn from_file_name(file_name: &str) -> Self {
+        static ENA_NAME: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"^[^_.]+(?:_(\d+))?\.f(?:ast)?q(?:\.gz|\.bz2)?$").unwrap()
+        });
+        static ILLUMINA_NAME: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"^.+_([RI])(\d)(?:_\d{3})?\.f(?:ast)?q(?:\.gz|\.bz2)?$").unwrap()
+        });
+        if let Some(captures) = ENA_NAME.captures(file_name) {
+            return match captures.get(1).map(|n| n.as_str().parse::<u8>()) {
+                None => ReadRole::Single,
+                Some(Ok(1)) => ReadRole::R1,
+                Some(Ok(2)) => ReadRole::R2,
+                Some(Ok(n)) if n >= 3 => ReadRole::Index(n),
+                Some(_) => ReadRole::Unknown,
+            };
+        }
+        match ILLUMINA_NAME.captures(file_name) {
+            // Index reads are numbered after the reads, as ENA numbers them
+            Some(captures) => match (&captures[1], &captures[2]) {
+                ("R", "1") => ReadRole::R1,
+                ("R", "2") => ReadRole::R2,
+                ("I", "1") => ReadRole::Index(3),
+                ("I", "2") => ReadRole::Index(4),
+                _ => ReadRole::Unknown,
+            },
+            None => ReadRole::Unknown,
+        }
+    }
+
+    /// The suffix used for the role in the names of the csv columns and
+    /// variables, or None if the role is not known
+    pub fn suffix(&self) -> Option<String> {
+        match self {
+            ReadRole::Single => Some("se".to_string()),
+            ReadRole::R1 => Some("1".to_string()),
+            ReadRole::R2 => Some("2".to_string()),
+            ReadRole::Index(n) => Some(n.to_string()),
+            ReadRole::Unknown => None,
+        }
+    }
+}
+
+/// The kinds of file ENA makes available for a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileType {
+    /// The FASTQ files generated by ENA
+    #[default]
+    Fastq,
+    /// The files as they were submitted, e.g., BAM or CRAM files
+    Submitted,
+    /// The SRA-format file
+    Sra,
+}
+
+impl FileType {
+    /// The name of the file type, as it is written in the output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileType::Fastq => "fastq",
+            FileType::Submitted => "submitted",
+            FileType::Sra => "sra",
+        }
+    }
+}
+
+/// The types of file to output for each run
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum FileTypeFilter {
+    Fastq,
+    Submitted,
+    Sra,
+    All,
+}
+
+impl FileTypeFilter {
+    /// Whether files of this type are selected
+    pub fn includes(&self, file_type: FileType) -> bool {
+        match self {
+            FileTypeFilter::Fastq => file_type == FileType::Fastq,
+            FileTypeFilter::Submitted => file_type == FileType::Submitted,
+            FileTypeFilter::Sra => file_type == FileType::Sra,
+            FileTypeFilter::All => true,
+        }
+    }
+}
+
+/// The order the runs are written out in
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RunOrder {
+    /// The order the accessions were given in, with the runs of a container
+    /// accession in the order ENA lists them
+    #[default]
+    Input,
+    /// Sorted by run accession
+    Accession,
+    /// The order the queries finished in, which changes from one query to
+    /// the next when there are concurrent requests
+    None,
+}
+
+impl RunOrder {
+    /// Put the runs in this order. The runs are expected in the order of the
+    /// accessions they were found for, or the order their queries finished
+    /// in for [`RunOrder::None`], which only sorting by accession changes
+    pub fn sort(&self, runs: &mut [Run]) {
+        if *self == RunOrder::Accession {
+            runs.sort_by(|a, b| a.accession.cmp(&b.accession));
         }
     }
 }
 
+/// The types of accession that the ENA API can resolve into runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessionType {
+    Run,
+    Experiment,
+    Sample,
+    Study,
+    Project,
+}
+
+impl AccessionType {
+    /// The patterns used to recognise each type of accession
+    const PATTERNS: [(AccessionType, &'static str); 5] = [
+        (AccessionType::Run, r"^(SRR|ERR|DRR)[0-9]{6,10}$"),
+        (AccessionType::Experiment, r"^(SRX|ERX|DRX)[0-9]{6,10}$"),
+        (
+            AccessionType::Sample,
+            r"^((SRS|ERS|DRS)[0-9]{6,10}|SAM(N|EA|D)[0-9]{6,10})$",
+        ),
+        (AccessionType::Study, r"^(SRP|ERP|DRP)[0-9]{6,10}$"),
+        (AccessionType::Project, r"^PRJ(NA|EB|DB)[0-9]{3,10}$"),
+    ];
+
+    /// Work out the type of an accession from its prefix, returning None
+    /// if the accession is not one we know how to query
+    pub fn from_accession(accession: &str) -> Option<Self> {
+        static REGEXES: LazyLock<Vec<(AccessionType, Regex)>> = LazyLock::new(|| {
+            AccessionType::PATTERNS
+                .iter()
+                .map(|(accession_type, pattern)| (*accession_type, Regex::new(pattern).unwrap()))
+                .collect()
+        });
+        REGEXES
+            .iter()
+            .find(|(_, regex)| regex.is_match(accession))
+            .map(|(accession_type, _)| *accession_type)
+    }
+
+    /// Whether the accession can contain more than one run
+    pub fn is_container(&self) -> bool {
+        *self != AccessionType::Run
+    }
+}
+
 #[derive(Debug, ValueEnum, Clone)]
 pub enum OutputFormat {
     Json,
+    /// One run per line as compact JSON (JSON Lines), written as the runs are found
+    Jsonl,
     Csv,
     CsvWide,
     CsvLong,
+    /// An nf-core samplesheet with the sample,fastq_1,fastq_2 columns
+    Samplesheet,
+    /// An nf-core samplesheet with the run metadata, like nf-core/fetchngs writes
+    SamplesheetFetchngs,
+    /// An input file for aria2c, with the name and MD5 of each file
+    Aria2,
+    /// A config file for curl
+    CurlConfig,
+    /// A bash script that downloads the files with wget and checks their MD5s
+    WgetScript,
+    /// The MD5s of the files, for md5sum -c
+    Md5sum,
+}
+
+impl OutputFormat {
+    /// The metadata fields that need to be requested from ENA to write out
+    /// the runs in this format
+    pub fn fields(&self) -> Vec<&'static str> {
+        match self {
+            OutputFormat::SamplesheetFetchngs => FETCHNGS_FIELDS.to_vec(),
+            _ => vec![],
+        }
+    }
 }
 
-/// Here, we implement the From trait for the Run struct, so that Run instances
+/// Here, we implement the TryFrom trait for the Run struct, so that Run instances
 /// can be derived from instances of the ENAApiResponse type.
 /// Full example here: https://play.rust-lang.org/?version=stable&mode=debug&edition=2021&gist=6d15ef7f0834dae23b1bcea336c627f2
-impl From<ENAApiResponse> for Run {
-    fn from(response: ENAApiResponse) -> Self {
-        let fastq_ftp_array = response.fastq_ftp.split(';').collect::<Vec<&str>>();
-        let fastq_bytes_array = response.fastq_bytes.split(';').collect::<Vec<&str>>();
-        let fastq_md5_array = response.fastq_md5.split(';').collect::<Vec<&str>>();
-        let mut reads: Vec<Reads> = Vec::new();
-        for i in 0..fastq_ftp_array.len() {
-            reads.push(Reads {
-                url: format!(
-                    "ftp://{address}",
-                    address = fastq_ftp_array[i].to_string().to_owned()
-                ),
-                bytes: match fastq_bytes_array[i].parse::<u32>() {
-                    Ok(n) => n,
-                    Err(_) => {
-                        eprintln!(
-                            "Could not parse {} as a number of bytes for accession {}",
-                            fastq_bytes_array[i], response.run_accession
-                        );
-                        0
-                    }
-                },
-                md5: fastq_md5_array[i].to_string().to_owned(),
-            });
-        }
-        Self {
+impl TryFrom<ENAApiResponse> for Run {
+    type Error = Error;
+
+    /// Collect the FASTQ, submitted and SRA-format files of the run, in that
+    /// order. The response is rejected if the lists of URLs, sizes and MD5s
+    /// for a type of file are not the same length, or if a size is not a
+    /// number, rather than reporting a file with the wrong size
+    fn try_from(response: ENAApiResponse) -> Result<Self, Error> {
+        let accession = response.run_accession.as_str();
+        let mut reads = parse_files(
+            accession,
+            FileType::Fastq,
+            &response.fastq_ftp,
+            &response.fastq_bytes,
+            &response.fastq_md5,
+        )?;
+        reads.extend(parse_files(
+            accession,
+            FileType::Submitted,
+            &response.submitted_ftp,
+            &response.submitted_bytes,
+            &response.submitted_md5,
+        )?);
+        reads.extend(parse_files(
+            accession,
+            FileType::Sra,
+            &response.sra_ftp,
+            &response.sra_bytes,
+            &response.sra_md5,
+        )?);
+        Ok(Self {
+            metadata: Metadata::from_fields(accession, response.extra),
             accession: response.run_accession,
+            parent_accession: None,
             reads,
-        }
+        })
     }
 }
 
+/// Split the semicolon separated URLs, sizes and MD5s ENA reports for one
+/// type of file into one `Reads` per file. A run without files of this type
+/// has empty fields, which gives no files
+fn parse_files(
+    accession: &str,
+    file_type: FileType,
+    ftp: &str,
+    bytes: &str,
+    md5: &str,
+) -> Result<Vec<Reads>, Error> {
+    if ftp.is_empty() {
+        return Ok(Vec::new());
+    }
+    let malformed = |message: String| Error::MalformedResponse {
+        accession: accession.to_owned(),
+        message,
+    };
+    let ftp_array = ftp.split(';').collect::<Vec<&str>>();
+    let bytes_array = bytes.split(';').collect::<Vec<&str>>();
+    let md5_array = md5.split(';').collect::<Vec<&str>>();
+    if bytes_array.len() != ftp_array.len() || md5_array.len() != ftp_array.len() {
+        return Err(malformed(format!(
+            "found {} {} files, but {} sizes and {} MD5s",
+            ftp_array.len(),
+            file_type.as_str(),
+            bytes_array.len(),
+            md5_array.len()
+        )));
+    }
+    let mut reads: Vec<Reads> = Vec::new();
+    for i in 0..ftp_array.len() {
+        reads.push(Reads {
+            url: format!("ftp://{address}", address = ftp_array[i]),
+            bytes: bytes_array[i].parse::<u64>().map_err(|_| {
+                malformed(format!(
+                    "could not parse {:?} as a number of bytes",
+                    bytes_array[i]
+                ))
+            })?,
+            md5: md5_array[i].to_owned(),
+            file_type,
+        });
+    }
+    Ok(reads)
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 struct Reads {
     url: String,
     md5: String,
-    bytes: u32,
+    bytes: u64,
+    /// Runs written out before the other types of file were supported only
+    /// have FASTQ files
+    #[serde(default)]
+    file_type: FileType,
 }
 
-/// A function to query the ENA API and return a vector of Run instances
-async fn query_ena(
-    accession: &String,
-    client: &reqwest::Client,
-) -> Result<Vec<Run>, reqwest::Error> {
-    let request_url = format!("https://www.ebi.ac.uk/ena/portal/api/filereport?accession={accession}&result=read_run&format=json", accession = accession);
-    let response = client.get(&request_url).send().await?;
-    let runs: Vec<Run> = response.json().await?;
-    Ok(runs)
+impl Reads {
+    /// The role of the file, worked out from its name
+    fn role(&self) -> ReadRole {
+        ReadRole::from_file_name(self.file_name())
+    }
 }
 
-/// A function to query the ENA API and return a vector of Run instances
-/// This function is used to query the ENA API concurrently across multiple accessions
-pub async fn concurrent_query_ena(accessions: Vec<String>, num_requests: usize) -> Vec<Run> {
-    let client = reqwest::Client::new();
-    futures::stream::iter({
-        accessions.iter().map(|accession| {
-            let client = client.clone();
-            eprintln!("Querying ENA for accession: {}", accession);
-            async move {
-                match query_ena(accession, &client).await {
-                    Ok(run) => Some(run),
-                    Err(e) => {
-                        eprintln!("Error querying ENA for accession: {}", accession);
-                        eprintln!("Error: {}", e);
-                        None
-                    }
-                }
-            }
-        })
-    })
-    .buffer_unordered(num_requests)
-    .collect::<Vec<_>>()
-    .await
-    .into_iter()
-    .filter_map(|run| run)
-    .flatten()
-    .collect::<Vec<Run>>()
-}
-
-/// CLI options and arguments
-#[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
-pub struct Args {
-    #[clap(short, long, value_parser, multiple = true, validator = validate_accession, required_unless_present = "file")]
-    /// The accession of the run to query (must be an SRR, ERR or DRR accession)
-    pub accession: Vec<String>,
-
-    #[clap(
-        short = 'n',
-        long = "num-requests",
-        value_name = "NUM",
-        default_value = "1",
-        help = "Maximum number of concurrent requests to make to the ENA API (max of 10 are allowed)"
-    )]
-    /// The maximum number of concurrent requests to make to the ENA API
-    /// Default: 1
-    /// Maximum: 10
-    /// Minimum: 1
-    pub num_requests: u8,
-
-    #[clap(
-        short,
-        long,
-        value_name = "FILE",
-        help = "File containing accessions to query",
-        required_unless_present = "accession"
-    )]
-    /// The file containing accessions to query
-    /// If this is specified, the accessions will be read from this file
-    /// If this is not specified, the accessions will be read from the command line
-    pub file: Option<PathBuf>,
-
-    #[clap(
-        short,
-        long,
-        help = "Keep single end reads if there are paired end reads too"
-    )]
-    /// Keep single end reads if there are paired end reads too
-    /// By default, we discard single end reads if there are paired end reads too.
-    /// This is if the user does wish to have the single end reads
-    pub keep_single_end: bool,
-
-    #[clap(
-        value_enum,
-        short = 'o',
-        long = "output-format",
-        value_name = "FORMAT",
-        default_value_t = OutputFormat::Json,
-        help = "Format for output of data."
-    )]
-    /// The ourput format for the download links
-    /// If this is specified, the data will be written to the output format
-    /// If this is not specified, the data will be written to stdout
-    pub format: OutputFormat,
-}
-
-pub fn parse_args() -> Args {
-    Args::parse()
-}
-
-/// Validate the accession number to make sure it starts with SRR, ERR,
-///  or DRR
-fn validate_accession(accession: &str) -> Result<(), String> {
-    let regex = regex::Regex::new(r"^(SRR|ERR|DRR)[0-9]{6,10}$").unwrap();
-    if regex.is_match(accession) {
+/// A function to query the ENA API concurrently across multiple accessions
+/// Every accession is reported, in the order it was requested, along with
+/// the runs that were found for it, or the reason why none were returned
+/// Failed requests are retried using the default retry policy
+pub async fn concurrent_query_ena(accessions: Vec<String>, num_requests: usize) -> QueryReport {
+    let client = EnaClient::builder()
+        .num_requests(num_requests)
+        .build()
+        .expect("the default client settings are valid");
+    client.resolve(accessions).await
+}
+
+/// Validate the accession number to make sure it is a run, experiment,
+/// sample, study or project accession that ENA can expand into runs
+fn validate_accession(accession: &str) -> Result<(), Error> {
+    if AccessionType::from_accession(accession).is_some() {
         Ok(())
     } else {
-        Err(format!("{} is not a valid accession number", accession))
+        Err(Error::InvalidAccession(accession.to_owned()))
     }
 }
 
@@ -209,117 +459,233 @@ fn validate_accession(accession: &str) -> Result<(), String> {
 ///
 pub fn check_num_requests(num_requests: u8) -> usize {
     if num_requests > 10 {
-        eprintln!("To be nice to ENA, we only allow up to 10 concurrent requests. Setting number of requests to 10.");
+        log::warn!("To be nice to ENA, we only allow up to 10 concurrent requests. Setting number of requests to 10.");
         10
     } else if num_requests < 1 {
-        eprintln!("Number of requests should be at least 1. Setting number of requests to 1.");
+        log::warn!("Number of requests should be at least 1. Setting number of requests to 1.");
         1
     } else {
         num_requests as usize
     }
 }
 
-/// A function to read accessions from a file and return a vector of validated
-/// accessions. The function skips any empty lines, and will issue a warning
-/// if it encounters an invalid accession. This deals with any potential header
-/// lines in the file.
-pub fn read_accessions(file: &PathBuf) -> Vec<String> {
-    let file = match File::open(file) {
-        Ok(file) => file,
-        Err(e) => {
-            eprintln!("Error opening file: {}", e);
-            exit(1);
+/// A column of a CSV or TSV file holding accessions, either by its name in
+/// the header line or by its position, counting from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessionColumn {
+    Name(String),
+    Index(usize),
+}
+
+impl std::str::FromStr for AccessionColumn {
+    type Err = String;
+
+    /// A number is the position of the column, anything else its name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(0) => Err("columns are counted from 1".to_string()),
+            Ok(index) => Ok(AccessionColumn::Index(index)),
+            Err(_) if s.trim().is_empty() => Err("the column name is empty".to_string()),
+            Err(_) => Ok(AccessionColumn::Name(s.to_string())),
         }
-    };
-    let reader = BufReader::new(file);
-    reader
-        .lines()
-        .into_iter()
-        .filter_map(|line| line.ok())
-        .filter_map(|line| if line.is_empty() { None } else { Some(line) })
-        .filter_map(|line| match validate_accession(line.as_str()) {
-            Ok(_) => Some(line),
-            Err(e) => {
-                eprintln!("Error validating accession: {}. Ignoring this value...", e);
-                None
-            }
-        })
-        .collect()
+    }
+}
+
+/// Open the file to read accessions from, or standard input if it is `-`
+fn open_accessions(file: &Path) -> Result<Box<dyn BufRead>, Error> {
+    if file == Path::new("-") {
+        Ok(Box::new(BufReader::new(std::io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(file)?)))
+    }
 }
 
-/// A function to handle output in the csv format. This function outputs one read per line.
-pub fn print_csv<W: std::io::Write>(wtr: &mut csv::Writer<W>, runs: Vec<Run>) -> Result<(), std::io::Error> {
-    for run in runs {
-        wtr.write_record(&["accession", "url", "md5", "bytes"])?;
-        for read in run.reads {
-            wtr.write_record(&[&run.accession, &read.url, &read.md5, &read.bytes.to_string()])?;
+/// Normalise an accession as it was given: a byte order mark, surrounding
+/// whitespace and a Windows line ending are dropped, and it is uppercased.
+/// Empty values and comments, which start with `#`, are not accessions at all
+pub(crate) fn normalise_accession(value: &str) -> Option<String> {
+    let value = value.trim_start_matches('\u{feff}').trim();
+    if value.is_empty() || value.starts_with('#') {
+        None
+    } else {
+        Some(value.to_ascii_uppercase())
+    }
+}
+
+/// Accessions as they were given, normalised, with the invalid ones left out
+/// and each accession kept once, in the order it was first given
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Accessions {
+    /// The valid accessions, each once
+    pub accessions: Vec<String>,
+    /// The accessions that were given more than once, each once
+    pub duplicates: Vec<String>,
+    /// The values that are not valid accessions, as they were given
+    pub invalid: Vec<String>,
+}
+
+impl Accessions {
+    /// Normalise, validate and deduplicate the values, skipping empty values
+    /// and comments
+    pub fn from_values<I, S>(values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut accessions = Accessions::default();
+        let mut seen = std::collections::HashSet::new();
+        for value in values {
+            let value = value.as_ref();
+            let Some(accession) = normalise_accession(value) else {
+                continue;
+            };
+            if validate_accession(&accession).is_err() {
+                accessions.invalid.push(value.trim().to_string());
+            } else if !seen.insert(accession.clone()) {
+                if !accessions.duplicates.contains(&accession) {
+                    accessions.duplicates.push(accession);
+                }
+            } else {
+                accessions.accessions.push(accession);
+            }
         }
+        accessions
     }
-    wtr.flush()?;
-    Ok(())
-}
-
-/// A function to handle output in the wide csv format. This function outputs one run per line.
-pub fn print_csv_wide<W: std::io::Write>(wtr: &mut csv::Writer<W>, runs: Vec<Run>, keep_single_end: bool) -> Result<(), std::io::Error> {
-    wtr.write_record(&["accession", "url_se", "md5_se", "bytes_1", "url_1", "md5_1", "bytes_se", "url_2", "md5_2", "bytes_2"])?;
-    for run in runs {
-        match run.reads.len() {
-            1 if keep_single_end==true => wtr.write_record(&[&run.accession, &run.reads[0].url,  &run.reads[0].md5, &run.reads[0].bytes.to_string(), "", "", "", "", "", ""])?,
-            2 => wtr.write_record(&[&run.accession, "", "", "", &run.reads[0].url, &run.reads[0].md5, &run.reads[0].bytes.to_string(), &run.reads[1].url, &run.reads[1].md5,  &run.reads[1].bytes.to_string()])?,
-            3 if keep_single_end==true => wtr.write_record(&[&run.accession, &run.reads[0].url, &run.reads[0].md5, &run.reads[0].bytes.to_string(), &run.reads[1].url, &run.reads[1].md5, &run.reads[1].bytes.to_string(), &run.reads[2].url, &run.reads[2].md5, &run.reads[2].bytes.to_string()])?,
-            _ => {
-                eprintln!("Found too many or too few reads for {}", &run.accession);
-                exit(1);
+
+    /// Warn about the invalid values and duplicated accessions that were left
+    /// out, with one summary for each rather than one warning per value
+    pub fn warn(&self) {
+        // Only the first few values are listed, as a whole file can be wrong
+        let list = |values: &[String]| {
+            let mut list = values.iter().take(5).cloned().collect::<Vec<_>>().join(", ");
+            if values.len() > 5 {
+                list.push_str(&format!(" and {} more", values.len() - 5));
             }
+            list
+        };
+        if !self.invalid.is_empty() {
+            log::warn!(
+                "Ignoring values that are not valid accessions ({}): {}",
+                self.invalid.len(),
+                list(&self.invalid)
+            );
+        }
+        if !self.duplicates.is_empty() {
+            log::warn!(
+                "Ignoring repeats of accessions given more than once ({}): {}",
+                self.duplicates.len(),
+                list(&self.duplicates)
+            );
         }
     }
-    wtr.flush()?;
-    Ok(())
 }
 
-///A function to handle output in the long csv format. This function prints one variable per line.
-pub fn print_csv_long<W: std::io::Write>(wtr: &mut csv::Writer<W>, runs: Vec<Run>) -> Result<(), std::io::Error> {
-    wtr.write_record(&["accession", "variable", "value"])?;
-    for run in runs {
-        match run.reads.len() {
-            1 => {
-                wtr.write_record(&[&run.accession, "url_se", &run.reads[0].url])?;
-                wtr.write_record(&[&run.accession, "md5_se", &run.reads[0].md5])?;
-                wtr.write_record(&[&run.accession, "bytes_se", &run.reads[0].bytes.to_string()])?; 
-            },
-            2 => {
-                wtr.write_record(&[&run.accession, "url_1", &run.reads[0].url])?;
-                wtr.write_record(&[&run.accession, "md5_1", &run.reads[0].md5])?;
-                wtr.write_record(&[&run.accession, "bytes_1", &run.reads[0].bytes.to_string()])?;
-                wtr.write_record(&[&run.accession, "url_2", &run.reads[1].url])?;
-                wtr.write_record(&[&run.accession, "md5_2", &run.reads[1].md5])?;
-                wtr.write_record(&[&run.accession, "bytes_2", &run.reads[1].bytes.to_string()])?;
-            },
-            3 => {
-                wtr.write_record(&[&run.accession, "url_se", &run.reads[0].url])?;
-                wtr.write_record(&[&run.accession, "md5_se", &run.reads[0].md5])?;
-                wtr.write_record(&[&run.accession, "bytes_se", &run.reads[0].bytes.to_string()])?; 
-                wtr.write_record(&[&run.accession, "url_1", &run.reads[1].url])?;
-                wtr.write_record(&[&run.accession, "md5_1", &run.reads[1].md5])?;
-                wtr.write_record(&[&run.accession, "bytes_1", &run.reads[1].bytes.to_string()])?;
-                wtr.write_record(&[&run.accession, "url_2", &run.reads[2].url])?;
-                wtr.write_record(&[&run.accession, "md5_2", &run.reads[2].md5])?;
-                wtr.write_record(&[&run.accession, "bytes_2", &run.reads[2].bytes.to_string()])?;
-            },
-            _ => {
-                eprintln!("Found too many or too few reads for {}", &run.accession);
-                exit(1);
+/// A function to read accessions from a file and return a vector of validated
+/// accessions. Accessions are normalised and each is kept once, in the order
+/// it first appears. The function skips any empty lines and comments, and
+/// will issue a warning summarising the invalid and duplicated accessions.
+/// This deals with any potential header lines in the file. Lines that are
+/// not valid UTF-8 are counted as invalid, rather than ending the file. A
+/// file named `-` is read from standard input.
+pub fn read_accessions(file: &Path) -> Result<Vec<String>, Error> {
+    let reader = open_accessions(file)?;
+    let lines = reader.split(b'\n').collect::<Result<Vec<_>, _>>()?;
+    let accessions =
+        Accessions::from_values(lines.iter().map(|line| String::from_utf8_lossy(line)));
+    accessions.warn();
+    Ok(accessions.accessions)
+}
+
+/// Read the accessions in a column of a CSV or TSV file, such as a sample
+/// sheet, normalised and deduplicated as by [`read_accessions`]. A column
+/// given by name is looked up in the header line. A column given by position
+/// can be read from a file with or without a header line, as a first value
+/// that is not an accession is skipped. Without a delimiter, the file is
+/// read as TSV if its first line has a tab in it, and as CSV otherwise. A
+/// file named `-` is read from standard input.
+pub fn read_accessions_column(
+    file: &Path,
+    column: &AccessionColumn,
+    delimiter: Option<u8>,
+) -> Result<Vec<String>, Error> {
+    let mut text = String::new();
+    open_accessions(file)?.read_to_string(&mut text)?;
+    let values = parse_accessions_column(&text, column, delimiter).map_err(|message| {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{}: {}", file.display(), message),
+        ))
+    })?;
+    let accessions = Accessions::from_values(values);
+    accessions.warn();
+    Ok(accessions.accessions)
+}
+
+/// Parse the values in a column of the text of a CSV or TSV file
+fn parse_accessions_column(
+    text: &str,
+    column: &AccessionColumn,
+    delimiter: Option<u8>,
+) -> Result<Vec<String>, String> {
+    let text = text.trim_start_matches('\u{feff}');
+    let delimiter = delimiter.unwrap_or_else(|| {
+        let first_line = text.lines().find(|line| !line.trim().is_empty());
+        if first_line.is_some_and(|line| line.contains('\t')) {
+            b'\t'
+        } else {
+            b','
+        }
+    });
+    let mut records = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes())
+        .into_records()
+        .peekable();
+    let index = match column {
+        AccessionColumn::Index(index) => {
+            // Skip a header line, whose value is not an accession
+            if let Some(Ok(first)) = records.peek() {
+                let value = first.get(index - 1).unwrap_or_default();
+                let accession = normalise_accession(value).unwrap_or_default();
+                if AccessionType::from_accession(&accession).is_none() {
+                    records.next();
+                }
             }
+            index - 1
         }
+        AccessionColumn::Name(name) => {
+            let header = match records.next() {
+                Some(header) => header.map_err(|e| e.to_string())?,
+                None => return Ok(Vec::new()),
+            };
+            header
+                .iter()
+                .position(|field| field == name)
+                .ok_or_else(|| format!("there is no {} column in the header line", name))?
+        }
+    };
+    let mut values = Vec::new();
+    for record in records {
+        let record = record.map_err(|e| e.to_string())?;
+        values.push(record.get(index).unwrap_or_default().to_string());
     }
-    wtr.flush()?;
-    Ok(())
+    Ok(values)
 }
 
+/// A function to read back runs that were written out by phcue-ck as JSON
+pub fn read_runs_json(file: &PathBuf) -> Result<Vec<Run>, Error> {
+    let reader = BufReader::new(File::open(file)?);
+    let runs = serde_json::from_reader(reader).map_err(std::io::Error::from)?;
+    Ok(runs)
+}
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use std::io::Write;
 
     #[test]
     fn test_validate_srr_accession() {
@@ -349,6 +715,132 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_validate_container_accessions() {
+        for accession in [
+            "SRX1234567",
+            "ERS1234567",
+            "SAMEA1234567",
+            "SAMN12345678",
+            "ERP123456",
+            "PRJEB12345",
+            "PRJNA123456",
+        ] {
+            assert!(validate_accession(accession).is_ok(), "{}", accession);
+        }
+    }
+
+    #[test]
+    fn test_read_accessions_missing_file() {
+        let result = read_accessions(&PathBuf::from("does/not/exist.txt"));
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_parse_accession_column() {
+        let sheet = "\u{feff}sample,run\nfirst,SRR1234567\nsecond,\nthird, ERR1234567 \n";
+        let column = AccessionColumn::Name("run".to_string());
+        assert_eq!(
+            parse_accessions_column(sheet, &column, None).unwrap(),
+            vec!["SRR1234567", "", "ERR1234567"]
+        );
+        let column = AccessionColumn::Name("accession".to_string());
+        assert!(parse_accessions_column(sheet, &column, None).is_err());
+        // By position, with or without a header line, and guessing tabs
+        let tsv = "SAMN00000001\tsrr1234567\nSAMN00000002\tnot an accession\n";
+        let column = AccessionColumn::Index(2);
+        assert_eq!(
+            parse_accessions_column(tsv, &column, None).unwrap(),
+            vec!["srr1234567", "not an accession"]
+        );
+        assert_eq!(
+            parse_accessions_column(sheet, &column, Some(b',')).unwrap(),
+            vec!["SRR1234567", "", "ERR1234567"]
+        );
+        let semicolons = "SRR1234567;x\nERR1234567;y\n";
+        let column = AccessionColumn::Index(1);
+        assert_eq!(
+            parse_accessions_column(semicolons, &column, Some(b';')).unwrap(),
+            vec!["SRR1234567", "ERR1234567"]
+        );
+    }
+
+    #[test]
+    fn test_accessions_from_values() {
+        let accessions = Accessions::from_values([
+            "\u{feff}srr1234567\r",
+            "# a comment",
+            "",
+            "  ERR1234567",
+            "SRR1234567",
+            "not an accession",
+            "Srr1234567",
+            "ERR1234567",
+        ]);
+        assert_eq!(accessions.accessions, vec!["SRR1234567", "ERR1234567"]);
+        assert_eq!(accessions.duplicates, vec!["SRR1234567", "ERR1234567"]);
+        assert_eq!(accessions.invalid, vec!["not an accession"]);
+    }
+
+    #[test]
+    fn test_read_accessions() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "accession\r\nSRR1234567\r\n\r\nsrr1234567\r\nERR1234567").unwrap();
+        assert_eq!(
+            read_accessions(file.path()).unwrap(),
+            vec!["SRR1234567", "ERR1234567"]
+        );
+    }
+
+    #[test]
+    fn test_read_accessions_invalid_utf8() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"SRR16298173\n# caf\xe9\nERR\xe9\nSRR16298174\n")
+            .unwrap();
+        assert_eq!(
+            read_accessions(file.path()).unwrap(),
+            vec!["SRR16298173", "SRR16298174"]
+        );
+    }
+
+    #[test]
+    fn test_accession_column_from_str() {
+        assert_eq!("2".parse(), Ok(AccessionColumn::Index(2)));
+        assert_eq!(
+            "run_accession".parse(),
+            Ok(AccessionColumn::Name("run_accession".to_string()))
+        );
+        assert!("0".parse::<AccessionColumn>().is_err());
+    }
+
+    #[test]
+    fn test_run_order() {
+        let mut runs = vec![
+            fastq_run(&["SRR1234567_1.fastq.gz"]),
+            fastq_run(&["SRR0000001_1.fastq.gz"]),
+        ];
+        runs[1].accession = "SRR0000001".to_string();
+        RunOrder::Accession.sort(&mut runs);
+        assert_eq!(runs[0].accession, "SRR0000001");
+    }
+
+    #[test]
+    fn test_accession_type() {
+        let cases = [
+            ("SRR1234567", AccessionType::Run),
+            ("DRX123456", AccessionType::Experiment),
+            ("SAMD00012345", AccessionType::Sample),
+            ("SRP123456", AccessionType::Study),
+            ("PRJDB1234", AccessionType::Project),
+        ];
+        for (accession, expected) in cases {
+            assert_eq!(AccessionType::from_accession(accession), Some(expected));
+        }
+        assert_eq!(AccessionType::from_accession("PRJXX1234"), None);
+        assert!(!AccessionType::Run.is_container());
+        assert!(AccessionType::Project.is_container());
+    }
+
     #[test]
     fn test_check_num_requests_valid() {
         let num_requests = 5;
@@ -370,39 +862,139 @@ mod tests {
         assert_eq!(result, 10);
     }
 
+    #[tokio::test]
+    async fn test_concurrent_query_ena_no_requests() {
+        let report = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            concurrent_query_ena(Vec::new(), 0),
+        )
+        .await
+        .expect("zero concurrent requests is taken as one");
+        assert!(report.outcomes.is_empty());
+    }
+
+    fn response(fastq_bytes: &str) -> ENAApiResponse {
+        ENAApiResponse {
+            run_accession: "SRR1234567".to_string(),
+            fastq_ftp: "ftp.sra.ebi.ac.uk/SRR1234567_1.fastq.gz;ftp.sra.ebi.ac.uk/SRR1234567_2.fastq.gz"
+                .to_string(),
+            fastq_bytes: fastq_bytes.to_string(),
+            fastq_md5: "md5_1;md5_2".to_string(),
+            submitted_ftp: String::new(),
+            submitted_md5: String::new(),
+            submitted_bytes: String::new(),
+            sra_ftp: String::new(),
+            sra_bytes: String::new(),
+            sra_md5: String::new(),
+            extra: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_run_from_response_metadata() {
+        let mut response = response("123;123");
+        response.extra.insert("tax_id".to_string(), "1280".into());
+        let run = Run::try_from(response).unwrap();
+        assert_eq!(run.metadata.tax_id, Some(1280));
+        assert_eq!(run.reads.len(), 2);
+    }
+
+    #[test]
+    fn test_run_from_response_large_files() {
+        let run = Run::try_from(response("5000000000;123")).unwrap();
+        assert_eq!(run.reads[0].bytes, 5_000_000_000);
+        assert_eq!(run.reads[0].url, "ftp://ftp.sra.ebi.ac.uk/SRR1234567_1.fastq.gz");
+        assert_eq!(run.reads[1].bytes, 123);
+    }
+
+    #[test]
+    fn test_run_from_response_malformed() {
+        assert!(matches!(
+            Run::try_from(response("123;not a number")),
+            Err(Error::MalformedResponse { .. })
+        ));
+        assert!(matches!(
+            Run::try_from(response("123")),
+            Err(Error::MalformedResponse { .. })
+        ));
+    }
+
+    #[test]
+    fn test_run_from_response_file_types() {
+        let mut response = response("123;456");
+        response.submitted_ftp = "ftp.sra.ebi.ac.uk/SRR1234567.bam".to_string();
+        response.submitted_bytes = "789".to_string();
+        response.submitted_md5 = "md5_bam".to_string();
+        response.sra_ftp = "ftp.sra.ebi.ac.uk/vol1/srr/SRR1234567".to_string();
+        response.sra_bytes = "1011".to_string();
+        response.sra_md5 = "md5_sra".to_string();
+        let mut run = Run::try_from(response).unwrap();
+        let file_types = run.reads.iter().map(|read| read.file_type).collect::<Vec<_>>();
+        assert_eq!(
+            file_types,
+            vec![FileType::Fastq, FileType::Fastq, FileType::Submitted, FileType::Sra]
+        );
+        assert_eq!(run.files(FileType::Submitted)[0].url, "ftp://ftp.sra.ebi.ac.uk/SRR1234567.bam");
+        run.select_file_types(&FileTypeFilter::Sra);
+        assert_eq!(run.reads.len(), 1);
+        assert_eq!(run.reads[0].bytes, 1011);
+    }
+
+    #[test]
+    fn test_run_from_response_without_fastq() {
+        let mut response = response("");
+        response.fastq_ftp = String::new();
+        response.fastq_md5 = String::new();
+        response.submitted_ftp = "ftp.sra.ebi.ac.uk/SRR1234567.cram".to_string();
+        response.submitted_bytes = "789".to_string();
+        response.submitted_md5 = "md5_cram".to_string();
+        let run = Run::try_from(response).unwrap();
+        assert_eq!(run.reads.len(), 1);
+        assert_eq!(run.reads[0].file_type, FileType::Submitted);
+    }
+
     #[test]
     fn test_removal_single_reads() {
         let read_se = Reads {
             url: "read.fastq.gz".to_string(),
             md5: "md5".to_string(),
             bytes: 123,
+            file_type: FileType::Fastq,
         };
         let read_pe_1 = Reads {
             url: "read_1.fastq.gz".to_string(),
             md5: "md5".to_string(),
             bytes: 123,
+            file_type: FileType::Fastq,
         };
         let read_pe_2 = Reads {
             url: "read_2.fastq.gz".to_string(),
             md5: "md5".to_string(),
             bytes: 123,
+            file_type: FileType::Fastq,
         };
         let reads_se = vec![read_se.clone()];
         let reads_pe = vec![read_pe_1.clone(), read_pe_2.clone()];
         let reads_pe_se = vec![read_se.clone(), read_pe_1.clone(), read_pe_2.clone()];
         let run_se = Run {
             accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
             reads: reads_se,
         };
         let run_pe = Run {
             accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
             reads: reads_pe,
         };
         let run_pe_se = Run {
             accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
             reads: reads_pe_se,
         };
-        let mut runs = vec![run_se, run_pe, run_pe_se];
+        let mut runs = [run_se, run_pe, run_pe_se];
         runs.iter_mut().for_each(|run| run.clean_single_end());
         assert_eq!(runs[0].reads[0], read_se);
         assert_eq!(runs[1].reads[0], read_pe_1);
@@ -412,125 +1004,111 @@ mod tests {
     }
 
     #[test]
-    fn test_print_csv() {
-        let read = Reads {
-            url: "url".to_string(),
-            md5: "md5".to_string(),
-            bytes: 123,
-        };
-        let reads = vec![read.clone()];
-        let run = Run {
-            accession: "accession".to_string(),
-            reads: reads,
-        };
-        let runs = vec![run];
-        let mut wtr = csv::Writer::from_writer(Vec::new());
-        print_csv(&mut wtr, runs).unwrap();
-        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
-        assert_eq!(data, "accession,url,md5,bytes\naccession,url,md5,123\n");
+    fn test_read_role() {
+        let cases = [
+            ("SRR1234567.fastq.gz", ReadRole::Single),
+            ("SRR1234567_1.fastq.gz", ReadRole::R1),
+            ("SRR1234567_2.fastq.gz", ReadRole::R2),
+            ("SRR1234567_3.fastq.gz", ReadRole::Index(3)),
+            ("SRR1234567_4.fq", ReadRole::Index(4)),
+            ("SRR1234567_0.fastq.gz", ReadRole::Unknown),
+            ("SRR1234567", ReadRole::Unknown),
+            ("sample.bam", ReadRole::Unknown),
+            ("sample_S1_R1_001.fastq.gz", ReadRole::R1),
+            ("sample_S1_L001_R2_001.fastq.gz", ReadRole::R2),
+            ("sample_S1_L001_I1_001.fastq.gz", ReadRole::Index(3)),
+            ("sample_R2.fq.gz", ReadRole::R2),
+            ("sample_S1_R3_001.fastq.gz", ReadRole::Unknown),
+        ];
+        for (file_name, expected) in cases {
+            assert_eq!(ReadRole::from_file_name(file_name), expected, "{}", file_name);
+        }
+        assert_eq!(ReadRole::Single.suffix().as_deref(), Some("se"));
+        assert_eq!(ReadRole::Index(3).suffix().as_deref(), Some("3"));
+        assert_eq!(ReadRole::Unknown.suffix(), None);
     }
 
-    #[test]
-    fn test_print_csv_wide() {
-        let read_se = Reads {
-            url: "url_se".to_string(),
-            md5: "md5_se".to_string(),
-            bytes: 123,
-        };
-        let read_pe_1 = Reads {
-            url: "url_pe_1".to_string(),
-            md5: "md5_pe_1".to_string(),
-            bytes: 123,
-        };
-        let read_pe_2 = Reads {
-            url: "url_pe_2".to_string(),
-            md5: "md5_pe_2".to_string(),
-            bytes: 123,
-        };
-        let reads_se = vec![read_se.clone()];
-        let reads_pe = vec![read_pe_1.clone(), read_pe_2.clone()];
-        let reads_pe_se = vec![read_se.clone(), read_pe_1.clone(), read_pe_2.clone()];
-        let run_se = Run {
-            accession: "SRR1234567".to_string(),
-            reads: reads_se,
-        };
-        let run_pe = Run {
-            accession: "SRR1234567".to_string(),
-            reads: reads_pe,
-        };
-        let run_pe_se = Run {
+    pub(crate) fn fastq_run(file_names: &[&str]) -> Run {
+        Run {
             accession: "SRR1234567".to_string(),
-            reads: reads_pe_se,
-        };
- 
-        let runs = vec![run_se];
-        let mut wtr = csv::Writer::from_writer(Vec::new());
-        print_csv_wide(&mut wtr, runs, true).unwrap();
-        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
-        assert_eq!(data, "accession,url_se,md5_se,bytes_1,url_1,md5_1,bytes_se,url_2,md5_2,bytes_2\nSRR1234567,url_se,md5_se,123,,,,,,\n");
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: file_names
+                .iter()
+                .map(|file_name| Reads {
+                    url: format!("ftp://ftp.sra.ebi.ac.uk/vol1/{}", file_name),
+                    md5: "md5".to_string(),
+                    bytes: 123,
+                    file_type: FileType::Fastq,
+                })
+                .collect(),
+        }
+    }
 
-        let runs_pe = vec![run_pe];
-        let mut wtr = csv::Writer::from_writer(Vec::new());
-        print_csv_wide(&mut wtr, runs_pe, false).unwrap();
-        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
-        assert_eq!(data, "accession,url_se,md5_se,bytes_1,url_1,md5_1,bytes_se,url_2,md5_2,bytes_2\nSRR1234567,,,,url_pe_1,md5_pe_1,123,url_pe_2,md5_pe_2,123\n");
+    #[test]
+    fn test_clean_single_end_any_order() {
+        let mut run = fastq_run(&["SRR1234567_1.fastq.gz", "SRR1234567_2.fastq.gz", "SRR1234567.fastq.gz"]);
+        run.clean_single_end();
+        let names = run.reads.iter().map(|read| read.file_name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["SRR1234567_1.fastq.gz", "SRR1234567_2.fastq.gz"]);
+
+        // Without a pair, there is nothing to drop
+        let mut run = fastq_run(&["SRR1234567.fastq.gz", "SRR1234567_1.fastq.gz"]);
+        run.clean_single_end();
+        assert_eq!(run.reads.len(), 2);
+    }
 
-        let runs_pe_se = vec![run_pe_se];
+    /// What a function printing runs in one of the csv formats writes
+    pub(crate) fn write_csv<F>(runs: Vec<Run>, print: F) -> String
+    where
+        F: FnOnce(&mut csv::Writer<Vec<u8>>, Vec<Run>) -> Result<(), Error>,
+    {
         let mut wtr = csv::Writer::from_writer(Vec::new());
-        print_csv_wide(&mut wtr, runs_pe_se, true).unwrap();
-        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
-        assert_eq!(data, "accession,url_se,md5_se,bytes_1,url_1,md5_1,bytes_se,url_2,md5_2,bytes_2\nSRR1234567,url_se,md5_se,123,url_pe_1,md5_pe_1,123,url_pe_2,md5_pe_2,123\n");
+        print(&mut wtr, runs).unwrap();
+        String::from_utf8(wtr.into_inner().unwrap()).unwrap()
     }
 
-    #[test]
-    fn test_print_csv_long() {
-        let read_se = Reads {
-            url: "url_se".to_string(),
-            md5: "md5_se".to_string(),
-            bytes: 123,
-        };
-        let read_pe_1 = Reads {
-            url: "url_pe_1".to_string(),
-            md5: "md5_pe_1".to_string(),
-            bytes: 123,
-        };
-        let read_pe_2 = Reads {
-            url: "url_pe_2".to_string(),
-            md5: "md5_pe_2".to_string(),
+    /// What a function printing runs as text, such as a download manifest,
+    /// writes
+    pub(crate) fn write_text<F>(runs: Vec<Run>, print: F) -> String
+    where
+        F: FnOnce(&mut Vec<u8>, Vec<Run>) -> Result<(), Error>,
+    {
+        let mut out = Vec::new();
+        print(&mut out, runs).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    pub(crate) fn run_with_other_files() -> Run {
+        let read = |url: &str, md5: &str, file_type| Reads {
+            url: url.to_string(),
+            md5: md5.to_string(),
             bytes: 123,
+            file_type,
         };
-        let reads_se = vec![read_se.clone()];
-        let reads_pe = vec![read_pe_1.clone(), read_pe_2.clone()];
-        let reads_pe_se = vec![read_se.clone(), read_pe_1.clone(), read_pe_2.clone()];
-        let run_se = Run {
-            accession: "SRR1234567".to_string(),
-            reads: reads_se,
-        };
-        let run_pe = Run {
+        Run {
             accession: "SRR1234567".to_string(),
-            reads: reads_pe,
-        };
-        let run_pe_se = Run {
-            accession: "SRR1234567".to_string(),
-            reads: reads_pe_se,
-        };
- 
-        let runs = vec![run_se];
-        let mut wtr = csv::Writer::from_writer(Vec::new());
-        print_csv_long(&mut wtr, runs).unwrap();
-        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
-        assert_eq!(data, "accession,variable,value\nSRR1234567,url_se,url_se\nSRR1234567,md5_se,md5_se\nSRR1234567,bytes_se,123\n");
-
-        let runs_pe = vec![run_pe];
-        let mut wtr = csv::Writer::from_writer(Vec::new());
-        print_csv_long(&mut wtr, runs_pe).unwrap();
-        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
-        assert_eq!(data, "accession,variable,value\nSRR1234567,url_1,url_pe_1\nSRR1234567,md5_1,md5_pe_1\nSRR1234567,bytes_1,123\nSRR1234567,url_2,url_pe_2\nSRR1234567,md5_2,md5_pe_2\nSRR1234567,bytes_2,123\n");
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: vec![
+                read("SRR1234567.fastq.gz", "md5_se", FileType::Fastq),
+                read("SRR1234567_1.fastq.gz", "md5_1", FileType::Fastq),
+                read("SRR1234567_2.fastq.gz", "md5_2", FileType::Fastq),
+                read("sample.bam", "md5_bam", FileType::Submitted),
+                read("sample.bam.bai", "md5_bai", FileType::Submitted),
+                read("SRR1234567", "md5_sra", FileType::Sra),
+            ],
+        }
+    }
 
-        let runs_pe_se = vec![run_pe_se];
-        let mut wtr = csv::Writer::from_writer(Vec::new());
-        print_csv_long(&mut wtr, runs_pe_se).unwrap();
-        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
-        assert_eq!(data, "accession,variable,value\nSRR1234567,url_se,url_se\nSRR1234567,md5_se,md5_se\nSRR1234567,bytes_se,123\nSRR1234567,url_1,url_pe_1\nSRR1234567,md5_1,md5_pe_1\nSRR1234567,bytes_1,123\nSRR1234567,url_2,url_pe_2\nSRR1234567,md5_2,md5_pe_2\nSRR1234567,bytes_2,123\n");
+    #[test]
+    fn test_clean_single_end_other_files() {
+        let mut run = run_with_other_files();
+        run.clean_single_end();
+        let urls = run.reads.iter().map(|read| read.url.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec!["SRR1234567_1.fastq.gz", "SRR1234567_2.fastq.gz", "sample.bam", "sample.bam.bai", "SRR1234567"]
+        );
     }
 }
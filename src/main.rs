@@ -1,67 +1,413 @@
 /// Get FTP address for FASTQ files given the accession number
 /// Example output from the API:
 /// {"run_accession":"SRR16298157","fastq_ftp":"ftp.sra.ebi.ac.uk/vol1/fastq/SRR162/057/SRR16298157/SRR16298157_1.fastq.gz;ftp.sra.ebi.ac.uk/vol1/fastq/SRR162/057/SRR16298157/SRR16298157_2.fastq.gz","fastq_bytes":"43409;42752","fastq_md5":"aaf5b365c1b45083c014baa35657b463;e80f09063bf017fa08b0dd881e840ed9","submitted_ftp":"","submitted_bytes":"","submitted_md5":"","sra_ftp":"ftp.sra.ebi.ac.uk/vol1/srr/SRR162/057/SRR16298157","sra_bytes":"157435","sra_md5":"baa98dd72f2a966be8f76569e46c03d9"}
-use phcue_ck::{check_num_requests, concurrent_query_ena, parse_args, read_accessions, print_csv, print_csv_wide, print_csv_long, Run, OutputFormat};
-use reqwest::Error;
+use phcue_ck::{check_num_requests, CacheAction, CacheArgs, download_runs, filter_runs, metadata_fields, parse_args, print_aria2, print_curl_config, print_md5sum, print_wget_script, print_csv_wide, print_csv_long, print_samplesheet, print_samplesheet_fetchngs, read_runs_json, verify_runs, Args, Command, DownloadArgs, EnaClient, Error, Filereport, QueryArgs, Run, OutputFormat, QueryReport, RunOrder, VerifyArgs, VerifyStatus, AccessionOutcome, CsvRunWriter, JsonlRunWriter, QueryOutcome, RunWriter};
+use futures::StreamExt;
+use std::fs::File;
 use std::process::exit;
 
+/// The exit code when any of the files fail verification, or a downloaded
+/// file does not match what ENA reports
+const VERIFY_FAILED: i32 = 8;
+
+/// Print the progress messages and warnings of the library to stderr, just
+/// as the messages, leaving out those of the crates it uses
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info && metadata.target().starts_with("phcue_ck")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
 #[tokio::main]
-async fn main() -> Result<(), Error> {
+async fn main() {
     openssl_probe::init_ssl_cert_env_vars();
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(log::LevelFilter::Info);
+    }
     let args = parse_args();
-    let accessions = match args.file {
-        Some(file) => read_accessions(&file),
-        None => args.accession,
+    match run(args).await {
+        Ok(0) => (),
+        Ok(code) => exit(code),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(exit_code(&e));
+        }
+    }
+}
+
+/// Map each kind of error to the exit code of the process, so scripts can
+/// tell the failures apart
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::InvalidAccession(_) => 2,
+        Error::Io(_) => 3,
+        Error::Http(_) => 4,
+        Error::MalformedResponse { .. } => 5,
+        Error::NotFound(_) => 7,
+        Error::NotAvailableOffline(_) => 9,
+        Error::SizeMismatch { .. } | Error::ChecksumMismatch { .. } => VERIFY_FAILED,
+        _ => 1,
+    }
+}
+
+async fn run(args: Args) -> Result<i32, Error> {
+    match args.command {
+        Some(Command::Download(download)) => download_command(download).await,
+        Some(Command::Verify(verify)) => verify_command(verify).await,
+        Some(Command::Cache(cache)) => cache_command(cache),
+        None => query_command(args).await,
+    }
+}
+
+/// Query ENA for the runs belonging to the accessions, reporting any
+/// accessions for which no runs were found or the query failed. Along with
+/// the runs, the exit code for the first failure is returned, so that the
+/// caller can carry on with the runs that were found before exiting
+/// If the runs were written out by phcue-ck before, they are read back in
+/// instead of querying ENA
+async fn resolve(query: &QueryArgs) -> Result<(Vec<Run>, i32), Error> {
+    if let Some(path) = &query.from_json {
+        let mut runs = read_runs_json(path)?;
+        select_files(&mut runs, query);
+        let mut runs = filter(runs, query);
+        query.order.sort(&mut runs);
+        return Ok((runs, 0));
+    }
+    let client = client(query)?;
+    let accessions = query.accessions()?;
+    let report = match query.order {
+        // Take the outcomes as they come, rather than waiting to put them back in order
+        RunOrder::None => QueryReport {
+            outcomes: client.resolve_stream(accessions).collect().await,
+        },
+        RunOrder::Input | RunOrder::Accession => client.resolve(accessions).await,
     };
-    let num_requests = check_num_requests(args.num_requests);
-    let mut runs: Vec<Run> = concurrent_query_ena(accessions, num_requests).await;
-    if !runs.is_empty() {
-        runs.sort_by(|a, b| a.accession.cmp(&b.accession));
-        if !args.keep_single_end {
-            runs.iter_mut().for_each(|run| run.clean_single_end());
+    if let Some(path) = &query.report {
+        report.write(File::create(path)?, &query.report_format)?;
+    }
+    for accession in report.not_found() {
+        eprintln!("No runs found in ENA for accession: {}", accession);
+    }
+    for (accession, e) in report.failures() {
+        eprintln!("Error querying ENA for accession {}: {}", accession, e);
+    }
+    let status = report.failures().next().map_or(0, |(_, e)| exit_code(e));
+    let mut runs = report.into_runs();
+    select_files(&mut runs, query);
+    let mut runs = filter(runs, query);
+    runs.iter_mut()
+        .for_each(|run| run.metadata.retain(&query.fields));
+    query.order.sort(&mut runs);
+    Ok((runs, status))
+}
+
+/// Build the client to query ENA with, as asked for on the command line
+fn client(query: &QueryArgs) -> Result<EnaClient, Error> {
+    let mut fields = query.fields.clone();
+    // Ask ENA for the fields the filter needs too, and drop them again after
+    if let Some(filter) = query.run_filter() {
+        for field in filter.fields() {
+            if !fields.iter().any(|f| f == field) {
+                fields.push(field.to_string());
+            }
         }
-        match args.format {
+    }
+    let filereport = match &query.filereport {
+        Some(path) => {
+            let filereport = Filereport::from_path(path)?;
+            let missing = filereport.missing_fields(&fields);
+            if !missing.is_empty() {
+                eprintln!(
+                    "The filereport {} has none of the fields {}, so the runs resolved from it have no value for them",
+                    path.display(),
+                    missing.join(", ")
+                );
+            }
+            Some(filereport)
+        }
+        None => None,
+    };
+    EnaClient::builder()
+        .base_url(query.ena_url.clone())
+        .endpoint(query.ena_endpoint.clone())
+        .result(query.ena_result.clone())
+        .retry(query.retry_policy())
+        .num_requests(check_num_requests(query.num_requests))
+        .cache(query.cache())
+        .filereport(filereport)
+        .offline(query.offline)
+        .fields(fields)
+        .build()
+}
+
+/// Query ENA for the runs belonging to the accessions, writing the runs for
+/// each accession out as soon as they are found, rather than once all the
+/// queries have finished, and returning the exit code for the first failure.
+/// Sorting the runs by accession needs all of them, so they are written out
+/// once they are all found, as are runs that were written out before
+async fn stream_runs(query: &QueryArgs, writer: &mut dyn RunWriter) -> Result<i32, Error> {
+    if query.order == RunOrder::Accession || query.from_json.is_some() {
+        let (runs, status) = resolve(query).await?;
+        for run in &runs {
+            writer.write_run(run)?;
+        }
+        writer.flush()?;
+        return Ok(status);
+    }
+    let client = client(query)?;
+    let accessions = query.accessions()?;
+    let mut outcomes = match query.order {
+        RunOrder::None => client.resolve_stream(accessions).boxed_local(),
+        RunOrder::Input => client.resolve_stream_in_order(accessions).boxed_local(),
+        RunOrder::Accession => unreachable!("runs sorted by accession are written once all are found"),
+    };
+    let filter = query.run_filter();
+    let (mut total, mut kept) = (0, 0);
+    let mut status = 0;
+    // Only hold on to the outcomes if they are needed for the report
+    let mut report = QueryReport::default();
+    while let Some(AccessionOutcome { accession, outcome }) = outcomes.next().await {
+        let outcome = match outcome {
+            QueryOutcome::Found(mut runs) => {
+                select_files(&mut runs, query);
+                for run in runs.iter_mut() {
+                    total += 1;
+                    if filter.as_ref().is_none_or(|filter| filter.matches(run)) {
+                        kept += 1;
+                        run.metadata.retain(&query.fields);
+                        writer.write_run(run)?;
+                    }
+                }
+                writer.flush()?;
+                QueryOutcome::Found(runs)
+            }
+            QueryOutcome::NotFound => {
+                eprintln!("No runs found in ENA for accession: {}", accession);
+                QueryOutcome::NotFound
+            }
+            QueryOutcome::Failed(e) => {
+                eprintln!("Error querying ENA for accession {}: {}", accession, e);
+                if status == 0 {
+                    status = exit_code(&e);
+                }
+                QueryOutcome::Failed(e)
+            }
+        };
+        if query.report.is_some() {
+            report.outcomes.push(AccessionOutcome { accession, outcome });
+        }
+    }
+    if filter.is_some() {
+        eprintln!("Filtered out {} of {} runs", total - kept, total);
+    }
+    if let Some(path) = &query.report {
+        report.write(File::create(path)?, &query.report_format)?;
+    }
+    Ok(status)
+}
+
+/// Drop the runs that do not match the filters on the command line
+fn filter(runs: Vec<Run>, query: &QueryArgs) -> Vec<Run> {
+    match query.run_filter() {
+        Some(filter) => {
+            let total = runs.len();
+            let runs = filter_runs(runs, &filter);
+            eprintln!("Filtered out {} of {} runs", total - runs.len(), total);
+            runs
+        }
+        None => runs,
+    }
+}
+
+/// Keep the files of the requested type, dropping the single end FASTQ
+/// files of paired end runs unless they were asked for
+fn select_files(runs: &mut [Run], query: &QueryArgs) {
+    for run in runs {
+        run.select_file_types(&query.file_type);
+        if !query.keep_single_end {
+            run.clean_single_end();
+        }
+    }
+}
+
+/// Query ENA and write out the runs that were found
+async fn query_command(args: Args) -> Result<i32, Error> {
+    let samplesheet = args.samplesheet_options()?;
+    let Args { mut query, format, compact, .. } = args;
+    // Ask ENA for the fields the output format needs as well. The samplesheet
+    // options only matter to the samplesheet formats, and other formats
+    // write out every field that is asked for
+    let samplesheet_fields = match format {
+        OutputFormat::Samplesheet | OutputFormat::SamplesheetFetchngs => samplesheet.fields(),
+        _ => vec![],
+    };
+    for field in format.fields().into_iter().chain(samplesheet_fields) {
+        if !query.fields.iter().any(|f| f == field) {
+            query.fields.push(field.to_string());
+        }
+    }
+    // The csv format can be written as the runs are found, unless they were
+    // written out before, and may have any metadata fields
+    if matches!(format, OutputFormat::Csv) && query.from_json.is_none() {
+        let mut writer = CsvRunWriter::new(std::io::stdout(), &query.fields)?;
+        let status = stream_runs(&query, &mut writer).await?;
+        if status == 0 {
+            eprintln!("CSV output completed successfully!");
+        }
+        return Ok(status);
+    }
+    if matches!(format, OutputFormat::Jsonl) {
+        let mut writer = JsonlRunWriter::new(std::io::stdout());
+        return stream_runs(&query, &mut writer).await;
+    }
+    let (runs, status) = resolve(&query).await?;
+    let dir = samplesheet.local_dir.as_deref();
+    if !runs.is_empty() {
+        match format {
+            OutputFormat::Json if compact => println!("{}", serde_json::to_string(&runs).unwrap()),
             OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&runs).unwrap()),
+            OutputFormat::Jsonl => unreachable!("the jsonl format is written as the runs are found"),
             OutputFormat::Csv => {
-                let mut wtr = csv::Writer::from_writer(std::io::stdout());
-                return match print_csv(&mut wtr, runs) {
-                    Ok(_) => {
-                        eprintln!("CSV output completed successfully!");
-                        Ok(())
-                    },
-                    Err(_) => {
-                        eprintln!("Error writing csv to stdout.");
-                        exit(1);
-                    }
+                // The same header as when the runs are written as they are
+                // found, or the fields the runs were written out with
+                let fields = if query.fields.is_empty() {
+                    metadata_fields(&runs)
+                } else {
+                    query.fields.clone()
                 };
+                let mut writer = CsvRunWriter::new(std::io::stdout(), &fields)?;
+                for run in &runs {
+                    writer.write_run(run)?;
+                }
+                writer.flush()?;
+                if status == 0 {
+                    eprintln!("CSV output completed successfully!");
+                }
             }
             OutputFormat::CsvWide => {
                 let mut wtr = csv::Writer::from_writer(std::io::stdout());
-                return match print_csv_wide(&mut wtr, runs, args.keep_single_end) {
-                    Ok(_) => {
-                        eprintln!("CSV output completed successfully!");
-                        Ok(())
-                    },
-                    Err(_) => {
-                        eprintln!("Error writing csv to stdout.");
-                        exit(1);
-                    }
-                };
+                print_csv_wide(&mut wtr, runs, query.keep_single_end)?;
+                if status == 0 {
+                    eprintln!("CSV output completed successfully!");
+                }
             }
             OutputFormat::CsvLong => {
                 let mut wtr = csv::Writer::from_writer(std::io::stdout());
-                return match print_csv_long(&mut wtr, runs) {
-                    Ok(_) => {
-                        eprintln!("CSV output completed successfully!");
-                        Ok(())
-                    },
-                    Err(_) => {
-                        eprintln!("Error writing csv to stdout.");
-                        exit(1);
-                    }
-                };
+                print_csv_long(&mut wtr, runs)?;
+                if status == 0 {
+                    eprintln!("CSV output completed successfully!");
+                }
             }
+            OutputFormat::Samplesheet => {
+                let mut wtr = csv::Writer::from_writer(std::io::stdout());
+                print_samplesheet(&mut wtr, runs, &samplesheet)?;
+                if status == 0 {
+                    eprintln!("CSV output completed successfully!");
+                }
+            }
+            OutputFormat::SamplesheetFetchngs => {
+                let mut wtr = csv::Writer::from_writer(std::io::stdout());
+                print_samplesheet_fetchngs(&mut wtr, runs, &samplesheet)?;
+                if status == 0 {
+                    eprintln!("CSV output completed successfully!");
+                }
+            }
+            OutputFormat::Aria2 => print_aria2(&mut std::io::stdout(), runs, dir)?,
+            OutputFormat::CurlConfig => print_curl_config(&mut std::io::stdout(), runs, dir)?,
+            OutputFormat::WgetScript => print_wget_script(&mut std::io::stdout(), runs, dir)?,
+            OutputFormat::Md5sum => print_md5sum(&mut std::io::stdout(), runs, dir)?,
+        }
+    }
+    Ok(status)
+}
+
+/// Query ENA and download the read files of the runs that were found
+async fn download_command(download: DownloadArgs) -> Result<i32, Error> {
+    let (runs, mut status) = resolve(&download.query).await?;
+    let num_requests = check_num_requests(download.query.num_requests);
+    let outcomes = download_runs(&runs, &download.output_dir, num_requests).await?;
+    for outcome in &outcomes {
+        match &outcome.result {
+            Ok(bytes) => eprintln!("Downloaded {} ({} bytes)", outcome.path.display(), bytes),
+            Err(e) => {
+                eprintln!("Error downloading {}: {}", outcome.url, e);
+                if status == 0 {
+                    status = exit_code(e);
+                }
+            }
+        }
+    }
+    Ok(status)
+}
+
+/// Check the read files of the runs in a directory against the sizes and
+/// MD5s reported by ENA, printing the result for each file. Files that
+/// cannot be read are reported as errors, and the rest are still checked
+async fn verify_command(verify: VerifyArgs) -> Result<i32, Error> {
+    let (runs, status) = resolve(&verify.query).await?;
+    let mut failed = false;
+    for outcome in verify_runs(&runs, &verify.dir) {
+        let path = outcome.path.display();
+        let result = match outcome.result {
+            Ok(result) => result,
+            Err(e) => {
+                failed = true;
+                println!("{}: ERROR ({})", path, e);
+                continue;
+            }
+        };
+        match result {
+            VerifyStatus::Ok => println!("{}: OK", path),
+            VerifyStatus::Missing => {
+                failed = true;
+                println!("{}: MISSING", path);
+            }
+            VerifyStatus::SizeMismatch { expected, actual } => {
+                failed = true;
+                println!("{}: MISMATCH (expected {} bytes, found {})", path, expected, actual);
+            }
+            VerifyStatus::Md5Mismatch { expected, actual } => {
+                failed = true;
+                println!("{}: MISMATCH (expected MD5 {}, found {})", path, expected, actual);
+            }
+        }
+    }
+    Ok(if failed { VERIFY_FAILED } else { status })
+}
+
+/// Clear the cache of responses from the ENA API, or show how much is in it
+fn cache_command(args: CacheArgs) -> Result<i32, Error> {
+    let cache = args.cache.cache().ok_or_else(|| {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no user cache directory, use --cache-dir",
+        ))
+    })?;
+    match args.action {
+        CacheAction::Clear => {
+            let removed = cache.clear()?;
+            eprintln!("Removed {} files from {}", removed, cache.dir.display());
+        }
+        CacheAction::Stats => {
+            let stats = cache.stats()?;
+            println!("Directory: {}", cache.dir.display());
+            println!("Responses: {}", stats.entries);
+            println!("Expired:   {}", stats.expired);
+            println!("Temporary: {}", stats.temporary);
+            println!("Size:      {} bytes", stats.bytes);
         }
     }
-    Ok(())
+    Ok(0)
 }
@@ -0,0 +1,211 @@
+use crate::{Error, Reads, Run};
+use std::io::Write;
+use std::path::Path;
+
+/// Where a file is written to, in a directory of its own for each run as
+/// the download command does, under the directory if there is one
+fn target(run: &Run, read: &Reads, dir: Option<&Path>) -> String {
+    let path = read.local_path(&run.accession);
+    match dir {
+        Some(dir) => dir.join(path).display().to_string(),
+        None => path.display().to_string(),
+    }
+}
+
+/// All the files of the runs, with the run of each, in the order they are
+/// listed
+fn files(runs: &[Run]) -> impl Iterator<Item = (&Run, &Reads)> {
+    runs.iter()
+        .flat_map(|run| run.reads.iter().map(move |read| (run, read)))
+}
+
+/// Escape a file name the way GNU md5sum does, so that names with a
+/// backslash or a newline are checked too. The line of an escaped name
+/// starts with a backslash
+fn md5sum_name(name: &str) -> (&'static str, String) {
+    if name.contains(['\\', '\n']) {
+        ("\\", name.replace('\\', r"\\").replace('\n', r"\n"))
+    } else {
+        ("", name.to_string())
+    }
+}
+
+/// Quote a value for the shell, so it is passed as a single argument
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Write an input file for aria2c, with the name and MD5 of each file as
+/// options under its URL, for use with `aria2c --input-file`. aria2c checks
+/// the MD5 once the file is downloaded
+pub fn print_aria2<W: Write>(wtr: &mut W, runs: Vec<Run>, dir: Option<&Path>) -> Result<(), Error> {
+    for (run, read) in files(&runs) {
+        writeln!(wtr, "{}", read.url)?;
+        if let Some(dir) = dir {
+            writeln!(wtr, "  dir={}", dir.display())?;
+        }
+        writeln!(wtr, "  out={}", target(run, read, None))?;
+        if !read.md5.is_empty() {
+            writeln!(wtr, "  checksum=md5={}", read.md5)?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Write a config file for curl, with the URL and output of each file, for
+/// use with `curl --config`. Interrupted downloads are carried on from where
+/// they stopped. curl does not check the MD5s, which can be checked with the
+/// md5sum format
+pub fn print_curl_config<W: Write>(
+    wtr: &mut W,
+    runs: Vec<Run>,
+    dir: Option<&Path>,
+) -> Result<(), Error> {
+    let quote = |value: &str| format!("\"{}\"", value.replace('\\', r"\\").replace('"', "\\\""));
+    writeln!(wtr, "# Download with: curl --config <this file>")?;
+    writeln!(wtr, "fail")?;
+    writeln!(wtr, "create-dirs")?;
+    writeln!(wtr, "continue-at = \"-\"")?;
+    for (run, read) in files(&runs) {
+        writeln!(wtr)?;
+        writeln!(wtr, "url = {}", quote(&read.url))?;
+        writeln!(wtr, "output = {}", quote(&target(run, read, dir)))?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Write a bash script that downloads each file with `wget -c`, so that
+/// running it again carries on with interrupted downloads, and then checks
+/// the files with `md5sum -c`. `wget -O` does not create directories, so the
+/// script makes the directory of each run first
+pub fn print_wget_script<W: Write>(
+    wtr: &mut W,
+    runs: Vec<Run>,
+    dir: Option<&Path>,
+) -> Result<(), Error> {
+    writeln!(wtr, "#!/usr/bin/env bash")?;
+    writeln!(wtr, "set -euo pipefail")?;
+    for run in runs.iter().filter(|run| !run.reads.is_empty()) {
+        let run_dir = match dir {
+            Some(dir) => dir.join(&run.accession),
+            None => Path::new(&run.accession).to_path_buf(),
+        };
+        writeln!(
+            wtr,
+            "mkdir -p {}",
+            shell_quote(&run_dir.display().to_string())
+        )?;
+    }
+    for (run, read) in files(&runs) {
+        writeln!(
+            wtr,
+            "wget -c -O {} {}",
+            shell_quote(&target(run, read, dir)),
+            shell_quote(&read.url)
+        )?;
+    }
+    writeln!(wtr, "md5sum -c <<'EOF'")?;
+    print_md5sum(wtr, runs, dir)?;
+    writeln!(wtr, "EOF")?;
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Write the MD5 and path of each file in the format `md5sum -c` checks.
+/// Files that ENA does not report an MD5 for are left out
+pub fn print_md5sum<W: Write>(
+    wtr: &mut W,
+    runs: Vec<Run>,
+    dir: Option<&Path>,
+) -> Result<(), Error> {
+    for (run, read) in files(&runs).filter(|(_, read)| !read.md5.is_empty()) {
+        let (prefix, name) = md5sum_name(&target(run, read, dir));
+        writeln!(wtr, "{}{}  {}", prefix, read.md5, name)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{fastq_run, write_text};
+
+    fn runs() -> Vec<Run> {
+        let mut paired = fastq_run(&["SRR1234567_1.fastq.gz", "SRR1234567_2.fastq.gz"]);
+        paired.reads[1].md5 = String::new();
+        let mut single = fastq_run(&["SRR0000001.fastq.gz"]);
+        single.accession = "SRR0000001".to_string();
+        let mut without_files = fastq_run(&[]);
+        without_files.accession = "SRR0000002".to_string();
+        vec![paired, single, without_files]
+    }
+
+    #[test]
+    fn test_print_aria2() {
+        assert_eq!(
+            write_text(runs(), |out, runs| print_aria2(
+                out,
+                runs,
+                Some(Path::new("reads"))
+            )),
+            "ftp://ftp.sra.ebi.ac.uk/vol1/SRR1234567_1.fastq.gz\n  dir=reads\n  \
+             out=SRR1234567/SRR1234567_1.fastq.gz\n  checksum=md5=md5\n\
+             ftp://ftp.sra.ebi.ac.uk/vol1/SRR1234567_2.fastq.gz\n  dir=reads\n  \
+             out=SRR1234567/SRR1234567_2.fastq.gz\n\
+             ftp://ftp.sra.ebi.ac.uk/vol1/SRR0000001.fastq.gz\n  dir=reads\n  \
+             out=SRR0000001/SRR0000001.fastq.gz\n  checksum=md5=md5\n"
+        );
+    }
+
+    #[test]
+    fn test_print_curl_config() {
+        let config = write_text(runs(), |out, runs| print_curl_config(out, runs, None));
+        assert!(config.contains(
+            "url = \"ftp://ftp.sra.ebi.ac.uk/vol1/SRR1234567_1.fastq.gz\"\n\
+             output = \"SRR1234567/SRR1234567_1.fastq.gz\"\n"
+        ));
+    }
+
+    #[test]
+    fn test_print_wget_script() {
+        let script = write_text(runs(), |out, runs| {
+            print_wget_script(out, runs, Some(Path::new("it's here")))
+        });
+        assert_eq!(
+            script,
+            "#!/usr/bin/env bash\nset -euo pipefail\n\
+             mkdir -p 'it'\\''s here/SRR1234567'\n\
+             mkdir -p 'it'\\''s here/SRR0000001'\n\
+             wget -c -O 'it'\\''s here/SRR1234567/SRR1234567_1.fastq.gz' 'ftp://ftp.sra.ebi.ac.uk/vol1/SRR1234567_1.fastq.gz'\n\
+             wget -c -O 'it'\\''s here/SRR1234567/SRR1234567_2.fastq.gz' 'ftp://ftp.sra.ebi.ac.uk/vol1/SRR1234567_2.fastq.gz'\n\
+             wget -c -O 'it'\\''s here/SRR0000001/SRR0000001.fastq.gz' 'ftp://ftp.sra.ebi.ac.uk/vol1/SRR0000001.fastq.gz'\n\
+             md5sum -c <<'EOF'\n\
+             md5  it's here/SRR1234567/SRR1234567_1.fastq.gz\n\
+             md5  it's here/SRR0000001/SRR0000001.fastq.gz\nEOF\n"
+        );
+    }
+
+    #[test]
+    fn test_print_md5sum() {
+        assert_eq!(
+            write_text(runs(), |out, runs| print_md5sum(out, runs, None)),
+            "md5  SRR1234567/SRR1234567_1.fastq.gz\nmd5  SRR0000001/SRR0000001.fastq.gz\n"
+        );
+    }
+
+    #[test]
+    fn test_print_md5sum_escaped() {
+        assert_eq!(
+            write_text(runs(), |out, runs| print_md5sum(
+                out,
+                runs,
+                Some(Path::new("back\\slash\nnewline"))
+            )),
+            "\\md5  back\\\\slash\\nnewline/SRR1234567/SRR1234567_1.fastq.gz\n\
+             \\md5  back\\\\slash\\nnewline/SRR0000001/SRR0000001.fastq.gz\n"
+        );
+    }
+}
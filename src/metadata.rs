@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Metadata about a run returned by the ENA API for the fields requested
+/// with `--fields`. The fields we know about are parsed into their types,
+/// and any other fields are kept as they are returned by ENA
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instrument_platform: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library_layout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library_strategy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scientific_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tax_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_public: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_accession: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub study_accession: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub experiment_accession: Option<String>,
+    /// Any other fields, by their name in the ENA API
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// The fields we parse into their types, in the order they are written out
+    const KNOWN_FIELDS: [&'static str; 11] = [
+        "instrument_platform",
+        "library_layout",
+        "library_strategy",
+        "scientific_name",
+        "tax_id",
+        "read_count",
+        "base_count",
+        "first_public",
+        "sample_accession",
+        "study_accession",
+        "experiment_accession",
+    ];
+
+    /// Set a field from the value returned by ENA. Empty values are treated
+    /// as missing, and a value that cannot be parsed into the type of a known
+    /// field is an error
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        if value.is_empty() {
+            return Ok(());
+        }
+        let text = || Some(value.to_string());
+        let number = || {
+            value
+                .parse::<u64>()
+                .map(Some)
+                .map_err(|_| format!("could not parse {:?} as a number for {}", value, name))
+        };
+        match name {
+            "instrument_platform" => self.instrument_platform = text(),
+            "library_layout" => self.library_layout = text(),
+            "library_strategy" => self.library_strategy = text(),
+            "scientific_name" => self.scientific_name = text(),
+            "tax_id" => self.tax_id = number()?,
+            "read_count" => self.read_count = number()?,
+            "base_count" => self.base_count = number()?,
+            "first_public" => self.first_public = text(),
+            "sample_accession" => self.sample_accession = text(),
+            "study_accession" => self.study_accession = text(),
+            "experiment_accession" => self.experiment_accession = text(),
+            _ => {
+                self.extra.insert(name.to_string(), value.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of a field, as it is written out
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "instrument_platform" => self.instrument_platform.clone(),
+            "library_layout" => self.library_layout.clone(),
+            "library_strategy" => self.library_strategy.clone(),
+            "scientific_name" => self.scientific_name.clone(),
+            "tax_id" => self.tax_id.map(|n| n.to_string()),
+            "read_count" => self.read_count.map(|n| n.to_string()),
+            "base_count" => self.base_count.map(|n| n.to_string()),
+            "first_public" => self.first_public.clone(),
+            "sample_accession" => self.sample_accession.clone(),
+            "study_accession" => self.study_accession.clone(),
+            "experiment_accession" => self.experiment_accession.clone(),
+            _ => self.extra.get(name).cloned(),
+        }
+    }
+
+    /// The names of the fields that are set, known fields first
+    pub fn names(&self) -> Vec<String> {
+        Self::KNOWN_FIELDS
+            .iter()
+            .filter(|name| self.get(name).is_some())
+            .map(|name| name.to_string())
+            .chain(self.extra.keys().cloned())
+            .collect()
+    }
+
+    /// Whether none of the fields are set
+    pub fn is_empty(&self) -> bool {
+        self.names().is_empty()
+    }
+
+    /// Keep only the fields that were asked for
+    pub fn retain(&mut self, fields: &[String]) {
+        let mut retained = Metadata::default();
+        for name in self.names() {
+            if fields.contains(&name) {
+                if let Some(value) = self.get(&name) {
+                    // The value was parsed from the same field, so it parses again
+                    let _ = retained.set(&name, &value);
+                }
+            }
+        }
+        *self = retained;
+    }
+
+    /// Build the metadata from the fields returned by ENA for a run, other
+    /// than the ones describing its files. A field whose value cannot be
+    /// parsed is left out with a warning, rather than failing the whole run
+    pub(crate) fn from_fields(
+        accession: &str,
+        fields: BTreeMap<String, serde_json::Value>,
+    ) -> Self {
+        let mut metadata = Metadata::default();
+        for (name, value) in fields {
+            let value = match value {
+                serde_json::Value::String(value) => value,
+                serde_json::Value::Null => String::new(),
+                value => value.to_string(),
+            };
+            if let Err(message) = metadata.set(&name, &value) {
+                log::warn!(
+                    "Leaving out a metadata field of run {}: {}",
+                    accession,
+                    message
+                );
+            }
+        }
+        metadata
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_fields() {
+        let fields = serde_json::from_str(
+            r#"{"tax_id":"1280","scientific_name":"Staphylococcus aureus","read_count":"","center_name":"UMIGS"}"#,
+        )
+        .unwrap();
+        let metadata = Metadata::from_fields("SRR1234567", fields);
+        assert_eq!(metadata.tax_id, Some(1280));
+        assert_eq!(
+            metadata.scientific_name.as_deref(),
+            Some("Staphylococcus aureus")
+        );
+        assert_eq!(metadata.read_count, None);
+        assert_eq!(metadata.extra["center_name"], "UMIGS");
+        assert_eq!(
+            metadata.names(),
+            vec!["scientific_name", "tax_id", "center_name"]
+        );
+    }
+
+    #[test]
+    fn test_from_fields_malformed() {
+        // The field that cannot be parsed is left out, and the rest are kept
+        let fields = serde_json::from_str(r#"{"base_count":"lots","tax_id":"1280"}"#).unwrap();
+        let metadata = Metadata::from_fields("SRR1234567", fields);
+        assert_eq!(metadata.base_count, None);
+        assert_eq!(metadata.tax_id, Some(1280));
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut metadata = Metadata::default();
+        metadata.set("tax_id", "1280").unwrap();
+        metadata.set("center_name", "UMIGS").unwrap();
+        metadata.set("study_title", "A study").unwrap();
+        metadata.retain(&["tax_id".to_string(), "study_title".to_string()]);
+        assert_eq!(metadata.names(), vec!["tax_id", "study_title"]);
+        metadata.retain(&[]);
+        assert!(metadata.is_empty());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut metadata = Metadata::default();
+        metadata.set("read_count", "100").unwrap();
+        metadata.set("center_name", "UMIGS").unwrap();
+        let json = serde_json::to_string(&metadata).unwrap();
+        assert_eq!(json, r#"{"read_count":100,"center_name":"UMIGS"}"#);
+        assert_eq!(serde_json::from_str::<Metadata>(&json).unwrap(), metadata);
+    }
+}
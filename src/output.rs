@@ -0,0 +1,1095 @@
+//! Writing runs out in the csv and json formats, and reading them back. The
+//! parent accessions of the runs are not written out, so they are not read
+//! back either
+use crate::{Error, FileType, ReadRole, Reads, Run};
+use std::io::{self, Read, Write};
+
+/// A field of a file, each of which gets a column or variable of its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Url,
+    Md5,
+    Bytes,
+}
+
+impl Field {
+    const ALL: [Field; 3] = [Field::Url, Field::Md5, Field::Bytes];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Field::Url => "url",
+            Field::Md5 => "md5",
+            Field::Bytes => "bytes",
+        }
+    }
+
+    fn value(&self, read: &Reads) -> String {
+        match self {
+            Field::Url => read.url.clone(),
+            Field::Md5 => read.md5.clone(),
+            Field::Bytes => read.bytes.to_string(),
+        }
+    }
+
+    /// Split a field off the start of a column or variable name, returning
+    /// the field and the rest of the name after the underscore
+    fn strip_prefix(name: &str) -> Option<(Field, &str)> {
+        Field::ALL.into_iter().find_map(|field| {
+            name.strip_prefix(field.name())
+                .and_then(|rest| rest.strip_prefix('_'))
+                .map(|rest| (field, rest))
+        })
+    }
+}
+
+/// A column of one of the csv formats
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Column {
+    /// The accession of the run
+    Accession,
+    /// A field of the file on the row, in the csv format
+    File(Field),
+    /// The type of the file on the row, in the csv format
+    FileType,
+    /// A field of the FASTQ file with this suffix, in the csv-wide format
+    Fastq(Field, String),
+    /// A field of all the files of a type other than FASTQ, separated by
+    /// semicolons, in the csv-wide format
+    Joined(FileType, Field),
+    /// The name of the variable on the row, in the csv-long format
+    Variable,
+    /// The value of the variable on the row, in the csv-long format
+    Value,
+    /// A metadata field of the run, in the csv and csv-wide formats
+    Metadata(String),
+}
+
+/// What a row of one of the csv formats is about
+enum Row<'a> {
+    /// One of the files of a run, or nothing for runs without any files,
+    /// in the csv format
+    File(&'a Run, Option<&'a Reads>),
+    /// A whole run, in the csv-wide format
+    Run(&'a Run, &'a [LayoutEntry<'a>]),
+    /// One of the variables of a run, in the csv-long format
+    Variable(&'a Run, String, String),
+}
+
+impl Column {
+    fn name(&self) -> String {
+        match self {
+            Column::Accession => "accession".to_string(),
+            Column::File(field) => field.name().to_string(),
+            Column::FileType => "file_type".to_string(),
+            Column::Fastq(field, suffix) => format!("{}_{}", field.name(), suffix),
+            Column::Joined(file_type, field) => {
+                format!("{}_{}", file_type.as_str(), field.name())
+            }
+            Column::Variable => "variable".to_string(),
+            Column::Value => "value".to_string(),
+            Column::Metadata(name) => name.clone(),
+        }
+    }
+
+    /// Work out the column from its name in the csv-wide format. Columns
+    /// that are not about the files are metadata fields
+    fn parse_wide(name: &str) -> Column {
+        if name == "accession" {
+            return Column::Accession;
+        }
+        for file_type in [FileType::Submitted, FileType::Sra] {
+            let field = name
+                .strip_prefix(file_type.as_str())
+                .and_then(|rest| rest.strip_prefix('_'))
+                .and_then(|rest| Field::ALL.into_iter().find(|field| field.name() == rest));
+            if let Some(field) = field {
+                return Column::Joined(file_type, field);
+            }
+        }
+        match Field::strip_prefix(name) {
+            Some((field, suffix)) => Column::Fastq(field, suffix.to_string()),
+            None => Column::Metadata(name.to_string()),
+        }
+    }
+
+    /// The value of the column on a row. Columns that do not belong to the
+    /// kind of row are left empty
+    fn value(&self, row: &Row) -> String {
+        match (self, row) {
+            (Column::Accession, Row::File(run, _) | Row::Run(run, _) | Row::Variable(run, ..)) => {
+                run.accession.clone()
+            }
+            (Column::File(field), Row::File(_, Some(read))) => field.value(read),
+            (Column::FileType, Row::File(_, Some(read))) => read.file_type.as_str().to_string(),
+            (Column::Fastq(field, suffix), Row::Run(_, layout)) => layout
+                .iter()
+                .find(|entry| entry.suffix == *suffix)
+                .map(|entry| field.value(entry.read))
+                .unwrap_or_default(),
+            (Column::Joined(file_type, field), Row::Run(run, _)) => run
+                .files(*file_type)
+                .into_iter()
+                .map(|read| field.value(read))
+                .collect::<Vec<_>>()
+                .join(";"),
+            (Column::Variable, Row::Variable(_, name, _)) => name.clone(),
+            (Column::Value, Row::Variable(_, _, value)) => value.clone(),
+            (Column::Metadata(name), Row::File(run, _) | Row::Run(run, _)) => {
+                run.metadata.get(name).unwrap_or_default()
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+/// The columns of one of the csv formats. The header and every row are
+/// generated from the same columns, so they cannot drift apart
+struct Schema {
+    columns: Vec<Column>,
+}
+
+impl Schema {
+    /// The csv format, with one file per row, and a column for each of the
+    /// metadata fields given. Whether the runs are written out all at once
+    /// or as they are found, the header comes from here
+    fn csv(fields: &[String]) -> Self {
+        let mut columns = vec![Column::Accession];
+        columns.extend(Field::ALL.map(Column::File));
+        columns.push(Column::FileType);
+        columns.extend(fields.iter().cloned().map(Column::Metadata));
+        Self { columns }
+    }
+
+    /// The csv-wide format, with one run per row. There are always columns
+    /// for single end and paired end FASTQ files, plus columns for any other
+    /// FASTQ files, any other types of file and any metadata fields that the
+    /// runs have
+    fn csv_wide(runs: &[Run], layouts: &[Vec<LayoutEntry>]) -> Self {
+        let mut extra = layouts
+            .iter()
+            .flatten()
+            .filter(|entry| !matches!(entry.role, ReadRole::Single | ReadRole::R1 | ReadRole::R2))
+            .map(|entry| (entry.key(), entry.suffix.clone()))
+            .collect::<Vec<_>>();
+        extra.sort();
+        extra.dedup();
+        let suffixes = ["se", "1", "2"]
+            .map(String::from)
+            .into_iter()
+            .chain(extra.into_iter().map(|(_, suffix)| suffix));
+        let mut columns = vec![Column::Accession];
+        for suffix in suffixes {
+            columns.extend(Field::ALL.map(|field| Column::Fastq(field, suffix.clone())));
+        }
+        for file_type in [FileType::Submitted, FileType::Sra] {
+            if runs.iter().any(|run| !run.files(file_type).is_empty()) {
+                columns.extend(Field::ALL.map(|field| Column::Joined(file_type, field)));
+            }
+        }
+        columns.extend(metadata_columns(runs));
+        Self { columns }
+    }
+
+    /// The csv-long format, with one variable per row
+    fn csv_long() -> Self {
+        Self {
+            columns: vec![Column::Accession, Column::Variable, Column::Value],
+        }
+    }
+
+    fn header(&self) -> Vec<String> {
+        self.columns.iter().map(Column::name).collect()
+    }
+
+    fn record(&self, row: &Row) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|column| column.value(row))
+            .collect()
+    }
+
+    /// Write the rows of a run in the csv format, one per file, or one for
+    /// the run itself if it has no files
+    fn write_csv_run<W: Write>(&self, wtr: &mut csv::Writer<W>, run: &Run) -> Result<(), Error> {
+        if run.reads.is_empty() {
+            wtr.write_record(self.record(&Row::File(run, None)))?;
+        }
+        for read in &run.reads {
+            wtr.write_record(self.record(&Row::File(run, Some(read))))?;
+        }
+        Ok(())
+    }
+
+    /// Check the header of a file starts with the columns this format is
+    /// written with, returning the names of the columns after them
+    fn check_header(&self, header: &csv::StringRecord) -> Result<Vec<String>, Error> {
+        let expected = self.header();
+        if header.len() < expected.len() || header.iter().zip(&expected).any(|(a, b)| a != b) {
+            return Err(invalid_data(format!("unexpected header {:?}", header)));
+        }
+        Ok(header
+            .iter()
+            .skip(expected.len())
+            .map(String::from)
+            .collect())
+    }
+}
+
+/// A column for each of the metadata fields that any of the runs have, in
+/// the order they are first seen
+fn metadata_columns(runs: &[Run]) -> Vec<Column> {
+    metadata_fields(runs)
+        .into_iter()
+        .map(Column::Metadata)
+        .collect()
+}
+
+/// The names of the metadata fields any of the runs have, in the order they
+/// first appear
+pub fn metadata_fields(runs: &[Run]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in runs.iter().flat_map(|run| run.metadata.names()) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+impl Run {
+    /// The FASTQ files of the run, ordered by role, along with the suffix
+    /// their columns or variables get in the csv formats. Files whose role
+    /// could not be worked out from their name, or which have the same role
+    /// as a file before them, are numbered by their position instead, e.g.,
+    /// file_4 for the fourth FASTQ file
+    fn fastq_layout(&self) -> Vec<LayoutEntry<'_>> {
+        let mut layout: Vec<LayoutEntry> = Vec::new();
+        for (i, read) in self.files(FileType::Fastq).into_iter().enumerate() {
+            let role = read.role();
+            let entry = match role.suffix() {
+                Some(suffix) if !layout.iter().any(|entry| entry.role == role) => LayoutEntry {
+                    role,
+                    position: 0,
+                    suffix,
+                    read,
+                },
+                _ => {
+                    let suffix = format!("file_{}", i + 1);
+                    log::warn!(
+                        "Could not work out the read number of {} for run {}, writing it as {}",
+                        read.file_name(),
+                        self.accession,
+                        suffix
+                    );
+                    LayoutEntry {
+                        role: ReadRole::Unknown,
+                        position: i + 1,
+                        suffix,
+                        read,
+                    }
+                }
+            };
+            layout.push(entry);
+        }
+        layout.sort_by_key(|entry| entry.key());
+        layout
+    }
+
+    /// The variables of the run in the csv-long format, followed by its
+    /// metadata fields. Runs without FASTQ files have a fastq_files variable
+    /// of 0, so they are not left out
+    fn variables(&self, layout: &[LayoutEntry]) -> Vec<(String, String)> {
+        let mut variables = Vec::new();
+        if layout.is_empty() {
+            variables.push(("fastq_files".to_string(), "0".to_string()));
+        }
+        for entry in layout {
+            for field in Field::ALL {
+                let column = Column::Fastq(field, entry.suffix.clone());
+                variables.push((column.name(), field.value(entry.read)));
+            }
+        }
+        for file_type in [FileType::Submitted, FileType::Sra] {
+            for (i, read) in self.files(file_type).into_iter().enumerate() {
+                for field in Field::ALL {
+                    let column = Column::Joined(file_type, field);
+                    variables.push((format!("{}_{}", column.name(), i + 1), field.value(read)));
+                }
+            }
+        }
+        for name in self.metadata.names() {
+            let value = self.metadata.get(&name).unwrap_or_default();
+            variables.push((name, value));
+        }
+        variables
+    }
+}
+
+/// A FASTQ file of a run, along with the suffix of its columns or variables
+/// in the csv formats
+struct LayoutEntry<'a> {
+    role: ReadRole,
+    /// The position of the file among the FASTQ files of the run, if it is
+    /// numbered by its position rather than its role, and 0 otherwise
+    position: usize,
+    suffix: String,
+    read: &'a Reads,
+}
+
+impl LayoutEntry<'_> {
+    /// The order the files are written in
+    fn key(&self) -> (ReadRole, usize) {
+        (self.role, self.position)
+    }
+}
+
+/// A function to handle output in the csv format. This function outputs one read per line.
+/// Runs without any files are written as a line with just the accession.
+/// There is a column for each of the metadata fields of the runs, as there is
+/// with [`CsvRunWriter`] given the [`metadata_fields`] of the runs.
+pub fn print_csv<W: Write>(wtr: &mut csv::Writer<W>, runs: Vec<Run>) -> Result<(), Error> {
+    let schema = Schema::csv(&metadata_fields(&runs));
+    wtr.write_record(schema.header())?;
+    for run in &runs {
+        schema.write_csv_run(wtr, run)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// A function to handle output in the wide csv format. This function outputs one run per line.
+/// Besides the single end and paired end columns, there are columns for the index reads and any
+/// other FASTQ files that any of the runs have, which are left empty for the runs without them.
+/// Submitted and SRA-format files get a url, md5 and bytes column each, if any of the runs
+/// have them, with the values for the files of a run separated by semicolons.
+pub fn print_csv_wide<W: Write>(
+    wtr: &mut csv::Writer<W>,
+    mut runs: Vec<Run>,
+    keep_single_end: bool,
+) -> Result<(), Error> {
+    if !keep_single_end {
+        runs.iter_mut().for_each(|run| run.clean_single_end());
+    }
+    let layouts = runs
+        .iter()
+        .map(|run| run.fastq_layout())
+        .collect::<Vec<_>>();
+    let schema = Schema::csv_wide(&runs, &layouts);
+    wtr.write_record(schema.header())?;
+    for (run, layout) in runs.iter().zip(&layouts) {
+        wtr.write_record(schema.record(&Row::Run(run, layout)))?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// A function to handle output in the long csv format. This function prints one variable per line.
+/// The FASTQ variables are named like the csv-wide columns, e.g., url_1, and submitted and
+/// SRA-format files are numbered in the order ENA lists them, e.g., submitted_url_1.
+pub fn print_csv_long<W: Write>(wtr: &mut csv::Writer<W>, runs: Vec<Run>) -> Result<(), Error> {
+    let schema = Schema::csv_long();
+    wtr.write_record(schema.header())?;
+    for run in &runs {
+        for (name, value) in run.variables(&run.fastq_layout()) {
+            wtr.write_record(schema.record(&Row::Variable(run, name, value)))?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes runs out one at a time, so that they can be written as soon as
+/// they are found, rather than once all of them are
+pub trait RunWriter {
+    /// Write out a run
+    fn write_run(&mut self, run: &Run) -> Result<(), Error>;
+
+    /// Make sure everything written so far is written through to the
+    /// underlying writer
+    fn flush(&mut self) -> Result<(), Error>;
+}
+
+/// Writes runs out in the csv format, one file per row. As the runs are not
+/// known when the header is written, there is a column for each of the
+/// metadata fields given, rather than for the fields the runs have
+pub struct CsvRunWriter<W: Write> {
+    wtr: csv::Writer<W>,
+    schema: Schema,
+}
+
+impl<W: Write> CsvRunWriter<W> {
+    /// Start writing runs in the csv format, writing the header straight away
+    pub fn new(writer: W, fields: &[String]) -> Result<Self, Error> {
+        let mut wtr = csv::Writer::from_writer(writer);
+        let schema = Schema::csv(fields);
+        wtr.write_record(schema.header())?;
+        Ok(Self { wtr, schema })
+    }
+}
+
+impl<W: Write> RunWriter for CsvRunWriter<W> {
+    fn write_run(&mut self, run: &Run) -> Result<(), Error> {
+        self.schema.write_csv_run(&mut self.wtr, run)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.wtr.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes runs out as JSON Lines (also known as NDJSON), with each run as a
+/// compact JSON object on a line of its own
+pub struct JsonlRunWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonlRunWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> RunWriter for JsonlRunWriter<W> {
+    fn write_run(&mut self, run: &Run) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.writer, run).map_err(io::Error::from)?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Read back runs written out in the csv format. Any columns after the ones
+/// describing the files are metadata fields
+pub fn read_csv<R: Read>(reader: R) -> Result<Vec<Run>, Error> {
+    let mut rdr = csv::Reader::from_reader(reader);
+    let schema = Schema::csv(&[]);
+    let metadata = schema.check_header(rdr.headers()?)?;
+    let mut runs: Vec<Run> = Vec::new();
+    for record in rdr.records() {
+        let record = record?;
+        let run = run_for(&mut runs, &record[0]);
+        let values = record.iter().skip(schema.columns.len());
+        for (name, value) in metadata.iter().zip(values) {
+            run.metadata.set(name, value).map_err(invalid_data)?;
+        }
+        if record[1].is_empty() {
+            continue;
+        }
+        let file_type = match &record[4] {
+            "fastq" => FileType::Fastq,
+            "submitted" => FileType::Submitted,
+            "sra" => FileType::Sra,
+            other => return Err(invalid_data(format!("unknown file type {:?}", other))),
+        };
+        run.reads.push(read_from_fields(
+            file_type, &record[1], &record[2], &record[3],
+        )?);
+    }
+    Ok(runs)
+}
+
+/// Read back runs written out in the csv-wide format. Any columns that are
+/// not about the files are metadata fields
+pub fn read_csv_wide<R: Read>(reader: R) -> Result<Vec<Run>, Error> {
+    let mut rdr = csv::Reader::from_reader(reader);
+    let columns = rdr
+        .headers()?
+        .iter()
+        .map(Column::parse_wide)
+        .collect::<Vec<_>>();
+    if columns.first() != Some(&Column::Accession) {
+        return Err(invalid_data(
+            "the first column is not the accession".to_string(),
+        ));
+    }
+    let mut runs = Vec::new();
+    for record in rdr.records() {
+        let record = record?;
+        let value = |wanted: &Column| {
+            columns
+                .iter()
+                .position(|column| column == wanted)
+                .map_or("", |i| &record[i])
+        };
+        let mut run = Run {
+            accession: record[0].to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: Vec::new(),
+        };
+        for (column, value) in columns.iter().zip(record.iter()) {
+            if let Column::Metadata(name) = column {
+                run.metadata.set(name, value).map_err(invalid_data)?;
+            }
+        }
+        for column in &columns {
+            if let Column::Fastq(Field::Url, suffix) = column {
+                if value(column).is_empty() {
+                    continue;
+                }
+                run.reads.push(read_from_fields(
+                    FileType::Fastq,
+                    value(column),
+                    value(&Column::Fastq(Field::Md5, suffix.clone())),
+                    value(&Column::Fastq(Field::Bytes, suffix.clone())),
+                )?);
+            }
+        }
+        for file_type in [FileType::Submitted, FileType::Sra] {
+            let urls = value(&Column::Joined(file_type, Field::Url));
+            if urls.is_empty() {
+                continue;
+            }
+            let md5s = value(&Column::Joined(file_type, Field::Md5)).split(';');
+            let bytes = value(&Column::Joined(file_type, Field::Bytes)).split(';');
+            for ((url, md5), bytes) in urls.split(';').zip(md5s).zip(bytes) {
+                run.reads
+                    .push(read_from_fields(file_type, url, md5, bytes)?);
+            }
+        }
+        runs.push(run);
+    }
+    Ok(runs)
+}
+
+/// Read back runs written out in the csv-long format. Any variables that are
+/// not about the files are metadata fields
+pub fn read_csv_long<R: Read>(reader: R) -> Result<Vec<Run>, Error> {
+    let mut rdr = csv::Reader::from_reader(reader);
+    let extra = Schema::csv_long().check_header(rdr.headers()?)?;
+    if !extra.is_empty() {
+        return Err(invalid_data(format!("unexpected columns {:?}", extra)));
+    }
+    // The fields of each file, keyed by the type of file and the rest of the
+    // variable name, in the order they are first seen
+    let mut files: Vec<(String, FileType, String, [String; 3])> = Vec::new();
+    let mut runs: Vec<Run> = Vec::new();
+    for record in rdr.records() {
+        let record = record?;
+        let (accession, variable, value) = (&record[0], &record[1], &record[2]);
+        let run = run_for(&mut runs, accession);
+        if variable == "fastq_files" {
+            continue;
+        }
+        let (file_type, rest) = [FileType::Submitted, FileType::Sra]
+            .into_iter()
+            .find_map(|file_type| {
+                variable
+                    .strip_prefix(file_type.as_str())
+                    .and_then(|rest| rest.strip_prefix('_'))
+                    .map(|rest| (file_type, rest))
+            })
+            .unwrap_or((FileType::Fastq, variable));
+        // FASTQ variables are named field_suffix, and the others type_field_n.
+        // Any other variables are metadata fields
+        let (field, key) = match Field::strip_prefix(rest) {
+            Some((field, key)) => (field, key),
+            None => {
+                run.metadata.set(variable, value).map_err(invalid_data)?;
+                continue;
+            }
+        };
+        let i = match files
+            .iter()
+            .position(|(a, t, k, _)| a == accession && *t == file_type && k == key)
+        {
+            Some(i) => i,
+            None => {
+                files.push((
+                    accession.to_string(),
+                    file_type,
+                    key.to_string(),
+                    Default::default(),
+                ));
+                files.len() - 1
+            }
+        };
+        let index = Field::ALL
+            .iter()
+            .position(|f| *f == field)
+            .unwrap_or_default();
+        files[i].3[index] = value.to_string();
+    }
+    for (accession, file_type, _, [url, md5, bytes]) in files {
+        let read = read_from_fields(file_type, &url, &md5, &bytes)?;
+        run_for(&mut runs, &accession).reads.push(read);
+    }
+    Ok(runs)
+}
+
+/// The run with the accession, adding a run with no files if there is not
+/// one yet
+fn run_for<'a>(runs: &'a mut Vec<Run>, accession: &str) -> &'a mut Run {
+    match runs.iter().position(|run| run.accession == accession) {
+        Some(i) => &mut runs[i],
+        None => {
+            runs.push(Run {
+                accession: accession.to_string(),
+                parent_accession: None,
+                metadata: Default::default(),
+                reads: Vec::new(),
+            });
+            runs.last_mut().unwrap()
+        }
+    }
+}
+
+fn read_from_fields(
+    file_type: FileType,
+    url: &str,
+    md5: &str,
+    bytes: &str,
+) -> Result<Reads, Error> {
+    Ok(Reads {
+        url: url.to_string(),
+        md5: md5.to_string(),
+        bytes: bytes.parse().map_err(|_| {
+            invalid_data(format!("could not parse {:?} as a number of bytes", bytes))
+        })?,
+        file_type,
+    })
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{fastq_run, run_with_other_files, write_csv};
+    use crate::FileTypeFilter;
+
+    #[test]
+    fn test_print_csv() {
+        let read = Reads {
+            url: "url".to_string(),
+            md5: "md5".to_string(),
+            bytes: 123,
+            file_type: FileType::Fastq,
+        };
+        let reads = vec![read.clone()];
+        let run = Run {
+            accession: "accession".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
+            reads,
+        };
+        let runs = vec![run];
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv(&mut wtr, runs).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            data,
+            "accession,url,md5,bytes,file_type\naccession,url,md5,123,fastq\n"
+        );
+    }
+
+    #[test]
+    fn test_print_csv_wide() {
+        let read_se = Reads {
+            url: "SRR1234567.fastq.gz".to_string(),
+            md5: "md5_se".to_string(),
+            bytes: 123,
+            file_type: FileType::Fastq,
+        };
+        let read_pe_1 = Reads {
+            url: "SRR1234567_1.fastq.gz".to_string(),
+            md5: "md5_pe_1".to_string(),
+            bytes: 123,
+            file_type: FileType::Fastq,
+        };
+        let read_pe_2 = Reads {
+            url: "SRR1234567_2.fastq.gz".to_string(),
+            md5: "md5_pe_2".to_string(),
+            bytes: 123,
+            file_type: FileType::Fastq,
+        };
+        let reads_se = vec![read_se.clone()];
+        let reads_pe = vec![read_pe_1.clone(), read_pe_2.clone()];
+        let reads_pe_se = vec![read_se.clone(), read_pe_1.clone(), read_pe_2.clone()];
+        let run_se = Run {
+            accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: reads_se,
+        };
+        let run_pe = Run {
+            accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: reads_pe,
+        };
+        let run_pe_se = Run {
+            accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: reads_pe_se,
+        };
+
+        let runs = vec![run_se];
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_wide(&mut wtr, runs, true).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "accession,url_se,md5_se,bytes_se,url_1,md5_1,bytes_1,url_2,md5_2,bytes_2\nSRR1234567,SRR1234567.fastq.gz,md5_se,123,,,,,,\n");
+
+        let runs_pe = vec![run_pe];
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_wide(&mut wtr, runs_pe, false).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "accession,url_se,md5_se,bytes_se,url_1,md5_1,bytes_1,url_2,md5_2,bytes_2\nSRR1234567,,,,SRR1234567_1.fastq.gz,md5_pe_1,123,SRR1234567_2.fastq.gz,md5_pe_2,123\n");
+
+        let runs_pe_se = vec![run_pe_se];
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_wide(&mut wtr, runs_pe_se, true).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "accession,url_se,md5_se,bytes_se,url_1,md5_1,bytes_1,url_2,md5_2,bytes_2\nSRR1234567,SRR1234567.fastq.gz,md5_se,123,SRR1234567_1.fastq.gz,md5_pe_1,123,SRR1234567_2.fastq.gz,md5_pe_2,123\n");
+    }
+
+    #[test]
+    fn test_print_csv_long() {
+        let read_se = Reads {
+            url: "SRR1234567.fastq.gz".to_string(),
+            md5: "md5_se".to_string(),
+            bytes: 123,
+            file_type: FileType::Fastq,
+        };
+        let read_pe_1 = Reads {
+            url: "SRR1234567_1.fastq.gz".to_string(),
+            md5: "md5_pe_1".to_string(),
+            bytes: 123,
+            file_type: FileType::Fastq,
+        };
+        let read_pe_2 = Reads {
+            url: "SRR1234567_2.fastq.gz".to_string(),
+            md5: "md5_pe_2".to_string(),
+            bytes: 123,
+            file_type: FileType::Fastq,
+        };
+        let reads_se = vec![read_se.clone()];
+        let reads_pe = vec![read_pe_1.clone(), read_pe_2.clone()];
+        let reads_pe_se = vec![read_se.clone(), read_pe_1.clone(), read_pe_2.clone()];
+        let run_se = Run {
+            accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: reads_se,
+        };
+        let run_pe = Run {
+            accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: reads_pe,
+        };
+        let run_pe_se = Run {
+            accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: reads_pe_se,
+        };
+
+        let runs = vec![run_se];
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_long(&mut wtr, runs).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "accession,variable,value\nSRR1234567,url_se,SRR1234567.fastq.gz\nSRR1234567,md5_se,md5_se\nSRR1234567,bytes_se,123\n");
+
+        let runs_pe = vec![run_pe];
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_long(&mut wtr, runs_pe).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "accession,variable,value\nSRR1234567,url_1,SRR1234567_1.fastq.gz\nSRR1234567,md5_1,md5_pe_1\nSRR1234567,bytes_1,123\nSRR1234567,url_2,SRR1234567_2.fastq.gz\nSRR1234567,md5_2,md5_pe_2\nSRR1234567,bytes_2,123\n");
+
+        let runs_pe_se = vec![run_pe_se];
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_long(&mut wtr, runs_pe_se).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "accession,variable,value\nSRR1234567,url_se,SRR1234567.fastq.gz\nSRR1234567,md5_se,md5_se\nSRR1234567,bytes_se,123\nSRR1234567,url_1,SRR1234567_1.fastq.gz\nSRR1234567,md5_1,md5_pe_1\nSRR1234567,bytes_1,123\nSRR1234567,url_2,SRR1234567_2.fastq.gz\nSRR1234567,md5_2,md5_pe_2\nSRR1234567,bytes_2,123\n");
+    }
+
+    #[test]
+    fn test_print_csv_long_unknown_files() {
+        let read = Reads {
+            url: "url".to_string(),
+            md5: "md5".to_string(),
+            bytes: 123,
+            file_type: FileType::Fastq,
+        };
+        let run = Run {
+            accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: vec![read.clone(), read.clone(), read.clone(), read],
+        };
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_long(&mut wtr, vec![run]).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data.lines().count(), 13);
+        assert!(data.contains("SRR1234567,url_file_4,url\n"));
+    }
+
+    #[test]
+    fn test_print_csv_long_by_role() {
+        let run = fastq_run(&[
+            "SRR1234567_3.fastq.gz",
+            "SRR1234567_2.fastq.gz",
+            "SRR1234567_1.fastq.gz",
+        ]);
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_long(&mut wtr, vec![run]).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        let variables = data
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split(',').nth(1))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            variables,
+            vec![
+                "url_1", "md5_1", "bytes_1", "url_2", "md5_2", "bytes_2", "url_3", "md5_3",
+                "bytes_3"
+            ]
+        );
+        assert!(
+            data.contains("SRR1234567,url_1,ftp://ftp.sra.ebi.ac.uk/vol1/SRR1234567_1.fastq.gz\n")
+        );
+    }
+
+    #[test]
+    fn test_print_csv_wide_extra_files() {
+        let runs = vec![
+            fastq_run(&[
+                "SRR1234567_1.fastq.gz",
+                "SRR1234567_2.fastq.gz",
+                "SRR1234567_3.fastq.gz",
+            ]),
+            fastq_run(&["SRR1234567_1.fastq.gz", "SRR1234567_1.fastq.gz"]),
+            fastq_run(&[]),
+        ];
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_wide(&mut wtr, runs, false).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        let lines = data.lines().collect::<Vec<_>>();
+        assert!(
+            lines[0].ends_with(",bytes_2,url_3,md5_3,bytes_3,url_file_2,md5_file_2,bytes_file_2")
+        );
+        assert!(
+            lines[1].ends_with(",ftp://ftp.sra.ebi.ac.uk/vol1/SRR1234567_3.fastq.gz,md5,123,,,")
+        );
+        assert!(
+            lines[2].ends_with(",,,,ftp://ftp.sra.ebi.ac.uk/vol1/SRR1234567_1.fastq.gz,md5,123")
+        );
+        assert_eq!(lines[3], "SRR1234567,,,,,,,,,,,,,,,");
+    }
+
+    #[test]
+    fn test_print_csv_without_files() {
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv(&mut wtr, vec![fastq_run(&[])]).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "accession,url,md5,bytes,file_type\nSRR1234567,,,,\n");
+
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_long(&mut wtr, vec![fastq_run(&[])]).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "accession,variable,value\nSRR1234567,fastq_files,0\n");
+    }
+
+    #[test]
+    fn test_print_csv_wide_other_files() {
+        let mut run = run_with_other_files();
+        run.clean_single_end();
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_wide(&mut wtr, vec![run], false).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "accession,url_se,md5_se,bytes_se,url_1,md5_1,bytes_1,url_2,md5_2,bytes_2,submitted_url,submitted_md5,submitted_bytes,sra_url,sra_md5,sra_bytes\nSRR1234567,,,,SRR1234567_1.fastq.gz,md5_1,123,SRR1234567_2.fastq.gz,md5_2,123,sample.bam;sample.bam.bai,md5_bam;md5_bai,123;123,SRR1234567,md5_sra,123\n");
+    }
+
+    #[test]
+    fn test_print_csv_long_other_files() {
+        let mut run = run_with_other_files();
+        run.select_file_types(&FileTypeFilter::Submitted);
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        print_csv_long(&mut wtr, vec![run]).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "accession,variable,value\nSRR1234567,fastq_files,0\nSRR1234567,submitted_url_1,sample.bam\nSRR1234567,submitted_md5_1,md5_bam\nSRR1234567,submitted_bytes_1,123\nSRR1234567,submitted_url_2,sample.bam.bai\nSRR1234567,submitted_md5_2,md5_bai\nSRR1234567,submitted_bytes_2,123\n");
+    }
+
+    /// Runs covering every layout, in the order the csv formats write their
+    /// files, so that they read back the same
+    fn runs() -> Vec<Run> {
+        let mut with_index = fastq_run(&[
+            "SRR1234567_1.fastq.gz",
+            "SRR1234567_2.fastq.gz",
+            "SRR1234567_3.fastq.gz",
+        ]);
+        with_index.accession = "SRR0000001".to_string();
+        with_index.metadata.set("tax_id", "1280").unwrap();
+        with_index.metadata.set("center_name", "UMIGS").unwrap();
+        let mut single_end = fastq_run(&["SRR1234567.fastq.gz"]);
+        single_end.accession = "SRR0000002".to_string();
+        let mut without_fastq = run_with_other_files();
+        without_fastq.accession = "SRR0000003".to_string();
+        without_fastq.select_file_types(&FileTypeFilter::Submitted);
+        let mut without_files = fastq_run(&[]);
+        without_files.accession = "SRR0000004".to_string();
+        without_files
+            .metadata
+            .set("scientific_name", "Staphylococcus aureus")
+            .unwrap();
+        vec![
+            with_index,
+            single_end,
+            run_with_other_files(),
+            without_fastq,
+            without_files,
+        ]
+    }
+
+    #[test]
+    fn test_rows_match_header() {
+        for data in [
+            write_csv(runs(), print_csv),
+            write_csv(runs(), |wtr, runs| print_csv_wide(wtr, runs, true)),
+            write_csv(runs(), print_csv_long),
+        ] {
+            let mut rdr = csv::Reader::from_reader(data.as_bytes());
+            let columns = rdr.headers().unwrap().len();
+            for record in rdr.records() {
+                assert_eq!(record.unwrap().len(), columns, "{}", data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wide_columns_line_up() {
+        let data = write_csv(runs(), |wtr, runs| print_csv_wide(wtr, runs, true));
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let header = rdr.headers().unwrap().clone();
+        for record in rdr.records() {
+            let record = record.unwrap();
+            for (name, value) in header.iter().zip(record.iter()) {
+                if let Some(suffix) = name.strip_prefix("url_") {
+                    // A FASTQ file is always in the columns for its read number
+                    if !value.is_empty() && !suffix.starts_with("file_") {
+                        let file_name = value.rsplit('/').next().unwrap();
+                        assert_eq!(
+                            ReadRole::from_file_name(file_name).suffix().unwrap(),
+                            suffix
+                        );
+                    }
+                }
+                if name.starts_with("bytes") && !value.is_empty() {
+                    assert!(
+                        value.split(';').all(|bytes| bytes.parse::<u64>().is_ok()),
+                        "{}",
+                        name
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip_csv() {
+        assert_eq!(
+            read_csv(write_csv(runs(), print_csv).as_bytes()).unwrap(),
+            runs()
+        );
+    }
+
+    #[test]
+    fn test_round_trip_csv_wide() {
+        let data = write_csv(runs(), |wtr, runs| print_csv_wide(wtr, runs, true));
+        assert_eq!(read_csv_wide(data.as_bytes()).unwrap(), runs());
+    }
+
+    #[test]
+    fn test_round_trip_csv_long() {
+        assert_eq!(
+            read_csv_long(write_csv(runs(), print_csv_long).as_bytes()).unwrap(),
+            runs()
+        );
+    }
+
+    #[test]
+    fn test_metadata_columns() {
+        let data = write_csv(runs(), |wtr, runs| print_csv_wide(wtr, runs, true));
+        let header = data.lines().next().unwrap();
+        assert!(
+            header.ends_with(",tax_id,center_name,scientific_name"),
+            "{}",
+            header
+        );
+        let data = write_csv(runs(), print_csv_long);
+        assert!(data.contains("SRR0000001,tax_id,1280\n"), "{}", data);
+        assert!(data.contains("SRR0000004,scientific_name,Staphylococcus aureus\n"));
+    }
+
+    #[test]
+    fn test_csv_run_writer() {
+        let fields = ["tax_id", "scientific_name", "center_name"].map(String::from);
+        let mut out = Vec::new();
+        let mut writer = CsvRunWriter::new(&mut out, &fields).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        // The header is written before any of the runs
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "accession,url,md5,bytes,file_type,tax_id,scientific_name,center_name\n"
+        );
+        let mut writer = CsvRunWriter::new(Vec::new(), &fields).unwrap();
+        for run in runs() {
+            writer.write_run(&run).unwrap();
+        }
+        let data = String::from_utf8(writer.wtr.into_inner().unwrap()).unwrap();
+        // The same rows as the csv format, with the metadata columns in the
+        // order they were given
+        assert_eq!(
+            data.lines().count(),
+            write_csv(runs(), print_csv).lines().count()
+        );
+        assert!(data.contains(",fastq,1280,,UMIGS\n"), "{}", data);
+        assert!(data.ends_with("SRR0000004,,,,,,Staphylococcus aureus,\n"));
+    }
+
+    #[test]
+    fn test_csv_run_writer_matches_print_csv() {
+        // Given the fields the runs have, runs written out as they are found
+        // look the same as runs written out all at once
+        let mut writer = CsvRunWriter::new(Vec::new(), &metadata_fields(&runs())).unwrap();
+        for run in runs() {
+            writer.write_run(&run).unwrap();
+        }
+        let data = String::from_utf8(writer.wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, write_csv(runs(), print_csv));
+    }
+
+    #[test]
+    fn test_jsonl_run_writer() {
+        let mut writer = JsonlRunWriter::new(Vec::new());
+        for run in runs() {
+            writer.write_run(&run).unwrap();
+        }
+        let data = String::from_utf8(writer.writer).unwrap();
+        let lines = data.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), runs().len());
+        let run: Run = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(run, runs()[0]);
+    }
+
+    #[test]
+    fn test_read_unexpected_header() {
+        let result = read_csv("accession,url,md5\nSRR1234567,url,md5\n".as_bytes());
+        assert!(matches!(result, Err(Error::Io(_))));
+        let result = read_csv_long("accession,variable,value,colour\n".as_bytes());
+        assert!(matches!(result, Err(Error::Io(_))));
+        let result = read_csv_wide("accession,url_se,tax_id\nSRR1234567,,human\n".as_bytes());
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{Error, Run};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Write;
+
+/// What happened when ENA was queried for a single accession
+#[derive(Debug)]
+pub enum QueryOutcome {
+    /// ENA returned one or more runs for the accession
+    Found(Vec<Run>),
+    /// ENA returned an empty array for the accession
+    NotFound,
+    /// The query failed
+    Failed(Error),
+}
+
+impl QueryOutcome {
+    /// A short label for the outcome, used in the report files
+    pub fn status(&self) -> &'static str {
+        match self {
+            QueryOutcome::Found(_) => "found",
+            QueryOutcome::NotFound => "not_found",
+            QueryOutcome::Failed(_) => "failed",
+        }
+    }
+}
+
+impl From<Result<Vec<Run>, Error>> for QueryOutcome {
+    fn from(result: Result<Vec<Run>, Error>) -> Self {
+        match result {
+            Ok(runs) => QueryOutcome::Found(runs),
+            Err(Error::NotFound(_)) => QueryOutcome::NotFound,
+            Err(e) => QueryOutcome::Failed(e),
+        }
+    }
+}
+
+/// The outcome of querying ENA for one of the requested accessions
+#[derive(Debug)]
+pub struct AccessionOutcome {
+    pub accession: String,
+    pub outcome: QueryOutcome,
+}
+
+/// The formats the query report can be written in
+#[derive(Debug, ValueEnum, Clone)]
+pub enum ReportFormat {
+    Tsv,
+    Json,
+}
+
+/// A single line of the query report, as it is written to file
+#[derive(Serialize)]
+struct ReportRecord<'a> {
+    accession: &'a str,
+    status: &'static str,
+    runs: usize,
+    message: String,
+}
+
+/// The outcome of querying ENA for every requested accession, in the order
+/// the accessions were requested
+#[derive(Debug, Default)]
+pub struct QueryReport {
+    pub outcomes: Vec<AccessionOutcome>,
+}
+
+impl QueryReport {
+    /// All the runs that were found, across all accessions
+    pub fn runs(&self) -> impl Iterator<Item = &Run> {
+        self.outcomes.iter().flat_map(|o| match &o.outcome {
+            QueryOutcome::Found(runs) => runs.as_slice(),
+            _ => &[],
+        })
+    }
+
+    /// Consume the report, keeping only the runs that were found
+    pub fn into_runs(self) -> Vec<Run> {
+        self.outcomes
+            .into_iter()
+            .flat_map(|o| match o.outcome {
+                QueryOutcome::Found(runs) => runs,
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+
+    /// The accessions for which ENA returned no runs
+    pub fn not_found(&self) -> impl Iterator<Item = &str> {
+        self.outcomes
+            .iter()
+            .filter(|o| matches!(o.outcome, QueryOutcome::NotFound))
+            .map(|o| o.accession.as_str())
+    }
+
+    /// The accessions for which the query failed, along with the error
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &Error)> {
+        self.outcomes.iter().filter_map(|o| match &o.outcome {
+            QueryOutcome::Failed(e) => Some((o.accession.as_str(), e)),
+            _ => None,
+        })
+    }
+
+    /// Whether the query failed for any of the accessions
+    pub fn has_failures(&self) -> bool {
+        self.failures().next().is_some()
+    }
+
+    fn records(&self) -> impl Iterator<Item = ReportRecord<'_>> {
+        self.outcomes.iter().map(|o| ReportRecord {
+            accession: &o.accession,
+            status: o.outcome.status(),
+            runs: match &o.outcome {
+                QueryOutcome::Found(runs) => runs.len(),
+                _ => 0,
+            },
+            message: match &o.outcome {
+                QueryOutcome::Failed(e) => e.to_string(),
+                _ => String::new(),
+            },
+        })
+    }
+
+    /// Write the report as a tab separated file, with one accession per line
+    pub fn write_tsv<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let mut wtr = csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .from_writer(writer);
+        for record in self.records() {
+            wtr.serialize(record)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Write the report as a JSON array, with one object per accession
+    pub fn write_json<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let records = self.records().collect::<Vec<_>>();
+        serde_json::to_writer_pretty(&mut writer, &records).map_err(std::io::Error::from)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+
+    /// Write the report in the requested format
+    pub fn write<W: Write>(&self, writer: W, format: &ReportFormat) -> Result<(), Error> {
+        match format {
+            ReportFormat::Tsv => self.write_tsv(writer),
+            ReportFormat::Json => self.write_json(writer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> QueryReport {
+        let run: Run = serde_json::from_str(r#"{"accession":"SRR1234567","reads":[]}"#).unwrap();
+        QueryReport {
+            outcomes: vec![
+                AccessionOutcome {
+                    accession: "SRR1234567".to_string(),
+                    outcome: QueryOutcome::Found(vec![run]),
+                },
+                AccessionOutcome {
+                    accession: "SRR7654321".to_string(),
+                    outcome: QueryOutcome::NotFound,
+                },
+                AccessionOutcome {
+                    accession: "ERR1234567".to_string(),
+                    outcome: QueryOutcome::Failed(Error::MalformedResponse {
+                        accession: "ERR1234567".to_string(),
+                        message: "oops".to_string(),
+                    }),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_report_summaries() {
+        let report = report();
+        assert_eq!(report.runs().count(), 1);
+        assert_eq!(report.not_found().collect::<Vec<_>>(), vec!["SRR7654321"]);
+        assert!(report.has_failures());
+        assert_eq!(report.failures().next().unwrap().0, "ERR1234567");
+        assert_eq!(report.into_runs()[0].accession, "SRR1234567");
+    }
+
+    #[test]
+    fn test_write_tsv() {
+        let mut out = Vec::new();
+        report().write_tsv(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "accession\tstatus\truns\tmessage\n\
+             SRR1234567\tfound\t1\t\n\
+             SRR7654321\tnot_found\t0\t\n\
+             ERR1234567\tfailed\t0\tCould not parse the ENA response for accession ERR1234567: oops\n"
+        );
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut out = Vec::new();
+        report().write_json(&mut out).unwrap();
+        let records: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(records[1]["status"], "not_found");
+        assert_eq!(records[2]["status"], "failed");
+        assert_eq!(records[0]["runs"], 1);
+    }
+}
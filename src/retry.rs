@@ -0,0 +1,146 @@
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use std::time::{Duration, SystemTime};
+
+/// How requests to the ENA API are retried when they fail with an error
+/// that is likely to go away, such as a dropped connection, a 5xx or a 429
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The number of times a request is retried after the first attempt fails
+    pub max_retries: u32,
+    /// The delay before the first retry. It doubles with every retry after that
+    pub base_delay: Duration,
+    /// The longest we will wait before retrying, including any delay the
+    /// ENA API asks for in a Retry-After header
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// The delay before the given retry (starting at 0). The delay doubles
+    /// with each retry, and a random jitter of up to half the delay is taken
+    /// off so that concurrent requests do not all retry at the same time
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+        delay.mul_f64(1.0 - jitter)
+    }
+
+    /// The delay before the given retry, honouring a Retry-After header sent
+    /// with the failed response, if there was one
+    pub(crate) fn delay(&self, retry: u32, response: Option<&Response>) -> Duration {
+        match response.and_then(retry_after) {
+            Some(delay) => delay.min(self.max_delay),
+            None => self.backoff(retry),
+        }
+    }
+}
+
+/// Whether a response with this status is worth retrying. Rate limiting,
+/// timeouts and server errors usually are, other client errors are not
+pub(crate) fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+/// Whether a failed request is worth retrying. Connection failures and
+/// timeouts are, but we give up straight away on a response we cannot decode
+pub(crate) fn is_retryable_error(error: &reqwest::Error) -> bool {
+    match error.status() {
+        Some(status) => is_retryable_status(status),
+        None => {
+            error.is_timeout() || error.is_connect() || error.is_request() || error.is_body()
+        }
+    }
+}
+
+/// Parse the Retry-After header of a response, which is either a number of
+/// seconds or an HTTP date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(SystemTime::now())
+            .ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(10),
+        };
+        for (retry, full) in [(0, 2), (1, 4), (2, 8), (3, 10), (20, 10)] {
+            let delay = policy.backoff(retry);
+            let full = Duration::from_secs(full);
+            assert!(delay <= full, "{:?} > {:?}", delay, full);
+            assert!(delay >= full / 2, "{:?} < {:?}", delay, full / 2);
+        }
+    }
+
+    #[test]
+    fn test_retryable_status() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+        assert!(!is_retryable_status(StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let response = |value: &str| {
+            Response::from(
+                http::Response::builder()
+                    .status(503)
+                    .header(RETRY_AFTER, value)
+                    .body("")
+                    .unwrap(),
+            )
+        };
+        assert_eq!(
+            retry_after(&response("120")),
+            Some(Duration::from_secs(120))
+        );
+        let later = SystemTime::now() + Duration::from_secs(300);
+        let delay = retry_after(&response(&httpdate::fmt_http_date(later))).unwrap();
+        assert!(delay > Duration::from_secs(290) && delay <= Duration::from_secs(300));
+        assert_eq!(retry_after(&response("soon")), None);
+
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            policy.delay(0, Some(&response("3600"))),
+            policy.max_delay
+        );
+    }
+}
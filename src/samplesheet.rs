@@ -0,0 +1,303 @@
+use crate::{AccessionType, Error, FileType, ReadRole, Reads, Run};
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// The metadata fields written to the fetchngs-style samplesheet, after the
+/// sample and FASTQ columns. They are requested from ENA when it is written
+pub const FETCHNGS_FIELDS: [&str; 8] = [
+    "experiment_accession",
+    "sample_accession",
+    "study_accession",
+    "library_layout",
+    "library_strategy",
+    "instrument_platform",
+    "read_count",
+    "base_count",
+];
+
+/// Where the sample column of a samplesheet comes from
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SampleFrom {
+    /// The run accession, so every run is a sample of its own
+    #[default]
+    Run,
+    /// The sample accession, so the runs of a sample are merged by the pipeline
+    Sample,
+}
+
+/// How the samplesheets are written
+#[derive(Debug, Clone, Default)]
+pub struct SamplesheetOptions {
+    /// Where the sample names come from, for runs that are not in `sample_names`
+    pub sample_from: SampleFrom,
+    /// Sample names by run or sample accession, which take precedence over
+    /// `sample_from`
+    pub sample_names: HashMap<String, String>,
+    /// The directory the files were downloaded into. If set, the FASTQ
+    /// columns are paths in the directory of each run in this directory
+    /// rather than URLs
+    pub local_dir: Option<PathBuf>,
+}
+
+impl SamplesheetOptions {
+    /// The name of the sample the run belongs to. nf-core pipelines do not
+    /// allow spaces in sample names, so they are replaced with underscores
+    fn sample(&self, run: &Run) -> String {
+        let sample_accession = run.metadata.sample_accession.as_ref();
+        let name = self
+            .sample_names
+            .get(&run.accession)
+            .or_else(|| sample_accession.and_then(|sample| self.sample_names.get(sample)))
+            .or(match self.sample_from {
+                SampleFrom::Run => None,
+                SampleFrom::Sample => sample_accession,
+            })
+            .unwrap_or_else(|| {
+                if self.sample_from == SampleFrom::Sample {
+                    log::warn!(
+                        "No sample accession for run {}, using the run accession as its sample",
+                        run.accession
+                    );
+                }
+                &run.accession
+            });
+        name.replace(' ', "_")
+    }
+
+    /// Where the pipeline should read the file from
+    fn location(&self, run: &Run, read: &Reads) -> String {
+        match &self.local_dir {
+            Some(dir) => dir
+                .join(read.local_path(&run.accession))
+                .display()
+                .to_string(),
+            None => read.url.clone(),
+        }
+    }
+
+    /// The metadata fields that need to be requested from ENA to write the
+    /// samplesheet. The sample accession is needed to name samples after it,
+    /// and to look up sample names keyed on it
+    pub fn fields(&self) -> Vec<&'static str> {
+        if self.sample_from == SampleFrom::Sample || !self.sample_names.is_empty() {
+            vec!["sample_accession"]
+        } else {
+            vec![]
+        }
+    }
+}
+
+impl Run {
+    /// The first and second FASTQ files of the run, with the second missing
+    /// for single end runs. Runs without single end or paired end FASTQ files
+    /// cannot go in a samplesheet
+    fn fastq_pair(&self) -> Option<(&Reads, Option<&Reads>)> {
+        let fastq = self.files(FileType::Fastq);
+        let find = |role| fastq.iter().copied().find(|read| read.role() == role);
+        match (find(ReadRole::R1), find(ReadRole::Single)) {
+            (Some(r1), _) => Some((r1, find(ReadRole::R2))),
+            (None, Some(single)) => Some((single, None)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Write the rows of a samplesheet, one per run with FASTQ files, with the
+/// sample and FASTQ columns followed by the extra columns
+fn print_rows<W: Write, F>(
+    wtr: &mut csv::Writer<W>,
+    runs: &[Run],
+    options: &SamplesheetOptions,
+    extra_columns: &[&str],
+    extra_values: F,
+) -> Result<(), Error>
+where
+    F: Fn(&Run, &Reads, Option<&Reads>) -> Vec<String>,
+{
+    let mut header = vec!["sample", "fastq_1", "fastq_2"];
+    header.extend(extra_columns);
+    wtr.write_record(header)?;
+    for run in runs {
+        let Some((first, second)) = run.fastq_pair() else {
+            log::warn!(
+                "Run {} has no single end or paired end FASTQ files, leaving it out of the samplesheet",
+                run.accession
+            );
+            continue;
+        };
+        let mut record = vec![
+            options.sample(run),
+            options.location(run, first),
+            second
+                .map(|read| options.location(run, read))
+                .unwrap_or_default(),
+        ];
+        record.extend(extra_values(run, first, second));
+        wtr.write_record(record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Write an nf-core samplesheet, with the sample and FASTQ columns that
+/// nf-core pipelines share: sample,fastq_1,fastq_2. Single end runs have an
+/// empty fastq_2, and runs without FASTQ files are left out
+pub fn print_samplesheet<W: Write>(
+    wtr: &mut csv::Writer<W>,
+    runs: Vec<Run>,
+    options: &SamplesheetOptions,
+) -> Result<(), Error> {
+    print_rows(wtr, &runs, options, &[], |_, _, _| Vec::new())
+}
+
+/// Write a samplesheet in the style of nf-core/fetchngs, with the columns of
+/// [`print_samplesheet`] followed by the run accession, the metadata fields
+/// in [`FETCHNGS_FIELDS`] and the MD5s of the FASTQ files
+pub fn print_samplesheet_fetchngs<W: Write>(
+    wtr: &mut csv::Writer<W>,
+    runs: Vec<Run>,
+    options: &SamplesheetOptions,
+) -> Result<(), Error> {
+    let mut columns = vec!["run_accession"];
+    columns.extend(FETCHNGS_FIELDS);
+    columns.extend(["md5_1", "md5_2"]);
+    print_rows(wtr, &runs, options, &columns, |run, first, second| {
+        let mut values = vec![run.accession.clone()];
+        values.extend(
+            FETCHNGS_FIELDS
+                .iter()
+                .map(|field| run.metadata.get(field).unwrap_or_default()),
+        );
+        values.push(first.md5.clone());
+        values.push(second.map(|read| read.md5.clone()).unwrap_or_default());
+        values
+    })
+}
+
+/// Read the sample names for run or sample accessions from a file with the
+/// accession and the sample name on each line, separated by a comma or a tab.
+/// A header line, whose first column is not an accession, is skipped
+pub fn read_sample_names(path: &Path) -> Result<HashMap<String, String>, Error> {
+    let reader = BufReader::new(std::fs::File::open(path)?);
+    let mut names = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((accession, name)) = line.split_once(['\t', ',']) else {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected an accession and a sample name on line {}", i + 1),
+            )));
+        };
+        let accession = accession.trim();
+        if i == 0 && AccessionType::from_accession(accession).is_none() {
+            continue;
+        }
+        names.insert(accession.to_string(), name.trim().to_string());
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{fastq_run, write_csv};
+
+    fn runs() -> Vec<Run> {
+        let mut paired = fastq_run(&["SRR1234567_1.fastq.gz", "SRR1234567_2.fastq.gz"]);
+        paired
+            .metadata
+            .set("sample_accession", "SAMN0000001")
+            .unwrap();
+        paired.metadata.set("library_layout", "PAIRED").unwrap();
+        let mut single = fastq_run(&["SRR0000001.fastq.gz"]);
+        single.accession = "SRR0000001".to_string();
+        let mut without_fastq = fastq_run(&[]);
+        without_fastq.accession = "SRR0000002".to_string();
+        vec![paired, single, without_fastq]
+    }
+
+    #[test]
+    fn test_print_samplesheet() {
+        let data = write_csv(runs(), |wtr, runs| {
+            print_samplesheet(wtr, runs, &SamplesheetOptions::default())
+        });
+        assert_eq!(
+            data,
+            "sample,fastq_1,fastq_2\n\
+             SRR1234567,ftp://ftp.sra.ebi.ac.uk/vol1/SRR1234567_1.fastq.gz,ftp://ftp.sra.ebi.ac.uk/vol1/SRR1234567_2.fastq.gz\n\
+             SRR0000001,ftp://ftp.sra.ebi.ac.uk/vol1/SRR0000001.fastq.gz,\n"
+        );
+    }
+
+    #[test]
+    fn test_print_samplesheet_local_by_sample() {
+        let options = SamplesheetOptions {
+            sample_from: SampleFrom::Sample,
+            sample_names: HashMap::from([("SRR0000001".to_string(), "my sample".to_string())]),
+            local_dir: Some(PathBuf::from("/data/reads")),
+        };
+        let data = write_csv(runs(), |wtr, runs| print_samplesheet(wtr, runs, &options));
+        assert_eq!(
+            data,
+            "sample,fastq_1,fastq_2\n\
+             SAMN0000001,/data/reads/SRR1234567/SRR1234567_1.fastq.gz,/data/reads/SRR1234567/SRR1234567_2.fastq.gz\n\
+             my_sample,/data/reads/SRR0000001/SRR0000001.fastq.gz,\n"
+        );
+    }
+
+    #[test]
+    fn test_fields() {
+        assert!(SamplesheetOptions::default().fields().is_empty());
+        let by_sample = SamplesheetOptions {
+            sample_from: SampleFrom::Sample,
+            ..Default::default()
+        };
+        assert_eq!(by_sample.fields(), vec!["sample_accession"]);
+        let mapped = SamplesheetOptions {
+            sample_names: HashMap::from([("SAMN0000001".to_string(), "first".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(mapped.fields(), vec!["sample_accession"]);
+    }
+
+    #[test]
+    fn test_print_samplesheet_fetchngs() {
+        let data = write_csv(runs(), |wtr, runs| {
+            print_samplesheet_fetchngs(wtr, runs, &SamplesheetOptions::default())
+        });
+        let mut lines = data.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "sample,fastq_1,fastq_2,run_accession,experiment_accession,sample_accession,\
+             study_accession,library_layout,library_strategy,instrument_platform,read_count,\
+             base_count,md5_1,md5_2"
+        );
+        assert!(lines
+            .next()
+            .unwrap()
+            .ends_with(",SRR1234567,,SAMN0000001,,PAIRED,,,,,md5,md5"));
+        assert!(lines.next().unwrap().ends_with(",SRR0000001,,,,,,,,,md5,"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn test_read_sample_names() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            "accession,sample\nSRR1234567,first\nSAMN0000001\tsecond"
+        )
+        .unwrap();
+        let names = read_sample_names(file.path()).unwrap();
+        assert_eq!(names.len(), 2);
+        assert_eq!(names["SRR1234567"], "first");
+        assert_eq!(names["SAMN0000001"], "second");
+        writeln!(file, "SRR0000001").unwrap();
+        assert!(matches!(read_sample_names(file.path()), Err(Error::Io(_))));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{Error, Reads, Run};
+use md5::{Digest, Md5};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// The result of checking a single read file against what ENA expects
+#[derive(Debug, PartialEq)]
+pub enum VerifyStatus {
+    /// The file has the expected size and MD5
+    Ok,
+    /// The file does not have the expected size, so its MD5 was not checked
+    SizeMismatch { expected: u64, actual: u64 },
+    /// The file has the expected size, but not the expected MD5
+    Md5Mismatch { expected: String, actual: String },
+    /// The file does not exist
+    Missing,
+}
+
+impl VerifyStatus {
+    /// Whether the file passed verification
+    pub fn is_ok(&self) -> bool {
+        *self == VerifyStatus::Ok
+    }
+}
+
+/// The outcome of verifying a single read file
+#[derive(Debug)]
+pub struct VerifyOutcome {
+    /// The accession of the run the file belongs to
+    pub accession: String,
+    /// The file that was checked
+    pub path: PathBuf,
+    /// The result of the check, or the reason the file could not be read
+    pub result: Result<VerifyStatus, Error>,
+}
+
+/// Check the read files of the runs, which are expected to be in the directory
+/// where the download subcommand puts them, in a directory for each run,
+/// against the sizes and MD5s reported by ENA. Each file is streamed through
+/// MD5, so they do not need to fit in memory. Files that ENA does not report
+/// an MD5 for are only checked for their size.
+pub fn verify_runs(runs: &[Run], dir: &Path) -> Vec<VerifyOutcome> {
+    runs.iter()
+        .flat_map(|run| {
+            run.reads.iter().map(|read| {
+                let path = dir.join(read.local_path(&run.accession));
+                VerifyOutcome {
+                    accession: run.accession.clone(),
+                    result: verify_file(read, &path),
+                    path,
+                }
+            })
+        })
+        .collect()
+}
+
+fn verify_file(read: &Reads, path: &Path) -> Result<VerifyStatus, Error> {
+    let size = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(VerifyStatus::Missing),
+        Err(e) => return Err(e.into()),
+    };
+    if size != read.bytes {
+        return Ok(VerifyStatus::SizeMismatch {
+            expected: read.bytes,
+            actual: size,
+        });
+    }
+    if read.md5.is_empty() {
+        return Ok(VerifyStatus::Ok);
+    }
+    let md5 = file_md5(path)?;
+    if !md5.eq_ignore_ascii_case(&read.md5) {
+        return Ok(VerifyStatus::Md5Mismatch {
+            expected: read.md5.clone(),
+            actual: md5,
+        });
+    }
+    Ok(VerifyStatus::Ok)
+}
+
+/// Calculate the MD5 of a file as a hex string, reading it in chunks
+pub(crate) fn file_md5(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileType;
+
+    const CONTENTS: &[u8] = b"@read\nACGT\n+\nIIII\n";
+    const MD5: &str = "0c0e1ec3b6a1a6c0e1b16f7c8d0e1e4f";
+
+    fn run(files: &[(&str, &str, u64)]) -> Run {
+        Run {
+            accession: "SRR1234567".to_string(),
+            parent_accession: None,
+            metadata: Default::default(),
+            reads: files
+                .iter()
+                .map(|(name, md5, bytes)| Reads {
+                    url: format!("ftp://ftp.sra.ebi.ac.uk/vol1/{}", name),
+                    md5: md5.to_string(),
+                    bytes: *bytes,
+                    file_type: FileType::Fastq,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_file_md5() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("read.fastq");
+        std::fs::write(&path, CONTENTS).unwrap();
+        assert_eq!(
+            file_md5(&path).unwrap(),
+            format!("{:x}", Md5::digest(CONTENTS))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_verify_runs_unreadable() {
+        let dir = tempfile::tempdir().unwrap();
+        let run_dir = dir.path().join("SRR1234567");
+        std::fs::create_dir(&run_dir).unwrap();
+        // A link to itself cannot be read, but the files after it are still
+        // checked
+        let looped = run_dir.join("looped_1.fastq");
+        std::os::unix::fs::symlink(&looped, &looped).unwrap();
+        let md5 = format!("{:x}", Md5::digest(CONTENTS));
+        std::fs::write(run_dir.join("ok_2.fastq"), CONTENTS).unwrap();
+        let run = run(&[("looped_1.fastq", &md5, 18), ("ok_2.fastq", &md5, 18)]);
+        let outcomes = verify_runs(&[run], dir.path());
+        assert!(matches!(outcomes[0].result, Err(Error::Io(_))));
+        assert_eq!(outcomes[1].result.as_ref().unwrap(), &VerifyStatus::Ok);
+    }
+
+    #[test]
+    fn test_verify_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let md5 = format!("{:x}", Md5::digest(CONTENTS));
+        std::fs::create_dir(dir.path().join("SRR1234567")).unwrap();
+        for name in [
+            "ok_1.fastq",
+            "no_md5_1.fastq",
+            "size_1.fastq",
+            "md5_1.fastq",
+        ] {
+            std::fs::write(dir.path().join("SRR1234567").join(name), CONTENTS).unwrap();
+        }
+        let run = run(&[
+            ("ok_1.fastq", &md5.to_uppercase(), 18),
+            ("no_md5_1.fastq", "", 18),
+            ("size_1.fastq", &md5, 20),
+            ("md5_1.fastq", MD5, 18),
+            ("missing_1.fastq", &md5, 18),
+        ]);
+        let statuses = verify_runs(&[run], dir.path())
+            .into_iter()
+            .map(|outcome| outcome.result.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![
+                VerifyStatus::Ok,
+                VerifyStatus::Ok,
+                VerifyStatus::SizeMismatch {
+                    expected: 20,
+                    actual: 18
+                },
+                VerifyStatus::Md5Mismatch {
+                    expected: MD5.to_string(),
+                    actual: md5
+                },
+                VerifyStatus::Missing,
+            ]
+        );
+    }
+}
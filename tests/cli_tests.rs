@@ -1,4 +1,46 @@
+use mockito::{Matcher, Mock, Server};
+
+/// The accessions there are saved ENA API responses for in tests/fixtures/ena
+const ACCESSIONS: [&str; 3] = ["SRR16298173", "SRR16298174", "ERR5556343"];
+
+/// Stand in for the ENA API, answering with the saved response for each of
+/// the accessions, and with an empty response, as ENA does, for SRR0000000
+fn mock_ena(server: &mut Server) -> Vec<Mock> {
+    let query = |accession: &str| {
+        Matcher::AllOf(vec![
+            Matcher::UrlEncoded("accession".into(), accession.into()),
+            Matcher::UrlEncoded("result".into(), "read_run".into()),
+            Matcher::UrlEncoded("format".into(), "json".into()),
+        ])
+    };
+    let mut mocks = ACCESSIONS
+        .iter()
+        .map(|accession| {
+            server
+                .mock("GET", "/filereport")
+                .match_query(query(accession))
+                .with_body_from_file(format!("tests/fixtures/ena/{}.json", accession))
+                .create()
+        })
+        .collect::<Vec<_>>();
+    mocks.push(
+        server
+            .mock("GET", "/filereport")
+            .match_query(query("SRR0000000"))
+            .with_body("")
+            .create(),
+    );
+    mocks
+}
+
 #[test]
 fn cli_tests() {
-    trycmd::TestCases::new().case("tests/cmd/*.trycmd");
+    let mut server = Server::new();
+    let _mocks = mock_ena(&mut server);
+    let cache = tempfile::tempdir().unwrap();
+    trycmd::TestCases::new()
+        .env("PHCUE_CK_ENA_URL", server.url())
+        .env("PHCUE_CK_CACHE_DIR", cache.path().display().to_string())
+        .case("tests/cmd/*.trycmd")
+        .case("tests/cmd/*.toml");
 }